@@ -0,0 +1,78 @@
+//blob.rs
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use crate::chain::Chain;
+use crate::spline::{draw_spline_polygon, SplineKind};
+use crate::util::Vector2;
+
+// Speed of the leading joint in pixels per second
+const SPEED: f32 = 360.0;
+
+#[derive(Clone)]
+pub struct Blob {
+    pub spine: Chain,
+}
+
+impl Blob {
+    pub fn new(origin: Vector2) -> Self {
+        let mut spine = Chain::ring(origin, 16, 40.0, std::f32::consts::PI / 4.0);
+        spine.collision_radius = 24.0;
+        spine.set_widths(&[24.0]);
+
+        Blob {
+            spine,
+        }
+    }
+
+    pub fn resolve(&mut self, mouse_x: f32, mouse_y: f32, dt: f32) {
+        let head_pos = self.spine.joints[0];
+        let mouse_pos = Vector2::new(mouse_x, mouse_y);
+        let to_mouse = mouse_pos - head_pos;
+        let target_pos = head_pos + to_mouse.set_mag((SPEED * dt).min(to_mouse.length()));
+        self.spine.resolve(target_pos, dt);
+    }
+
+    // Holds joint of the membrane at the given position, the rest of the ring follows it
+    pub fn drag(&mut self, joint: usize, x: f32, y: f32, dt: f32) {
+        self.spine.resolve_from(joint, Vector2::new(x, y), dt);
+    }
+
+    pub fn interpolate(&self, previous: &Blob, alpha: f32) -> Blob {
+        Blob {
+            spine: self.spine.interpolate(&previous.spine, alpha),
+        }
+    }
+
+    pub fn display(&self, canvas: &mut Canvas<Window>) {
+        // Membrane, pushed out from the middle of the ring
+        let center = self.center();
+        let points: Vec<(f32, f32)> = (0..self.spine.joints.len())
+            .map(|i| {
+                let joint = self.spine.joints[i];
+                let pos = joint + (joint - center).set_mag(self.spine.width(i));
+                (pos.x, pos.y)
+            })
+            .collect();
+        draw_spline_polygon(canvas, &points, SplineKind::BSpline, 0.5, true, Color::RGB(120, 170, 90));
+
+        self.draw_eyes(canvas);
+        //self.spine.display(canvas);
+    }
+
+    fn draw_eyes(&self, canvas: &mut Canvas<Window>) {
+        let center = self.center();
+        let forward = (self.spine.joints[0] - center).set_mag(40.0);
+        let side = Vector2::new(-forward.y, forward.x).set_mag(24.0);
+        let right_eye = center + forward + side;
+        let left_eye = center + forward - side;
+        canvas.filled_circle(right_eye.x as i16, right_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
+        canvas.filled_circle(left_eye.x as i16, left_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
+    }
+
+    fn center(&self) -> Vector2 {
+        let sum = self.spine.joints.iter().fold(Vector2::default(), |acc, &joint| acc + joint);
+        sum / self.spine.joints.len() as f32
+    }
+}
@@ -3,64 +3,835 @@ use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::pixels::Color;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
-use crate::util::{constrain_distance, constrain_angle, Vector2};
+use crate::collision::Shape;
+use crate::pose::Pose;
+use crate::spline::SpineCurve;
+use crate::util::{constrain_distance, Angle, Vector2};
 
+// Push-out and re-attach rounds per joint when resolving against obstacles
+const COLLISION_ITERATIONS: usize = 3;
+// Bend and relaxation rounds around the loop when resolving a ring, enough for a drag to reach the far side
+const RING_ITERATIONS: usize = 24;
+
+#[derive(Clone)]
 pub struct Chain {
     pub joints: Vec<Vector2>,
-    link_size: f32,
-    pub angles: Vec<f32>,
-    angle_constraint: f32,
+    prev_joints: Vec<Vector2>,
+    link_sizes: Vec<f32>,
+    pub angles: Vec<Angle>,
+    limits: Vec<JointLimit>,
+    pub solver: IkSolver,
+    pub tolerance: f32,
+    pub max_iterations: usize,
+    pub dynamics: Option<Dynamics>,
+    prev_dt: f32,
+    pub obstacles: Vec<Shape>,
+    // Joints are treated as circles of this radius when pushed out of obstacles
+    pub collision_radius: f32,
+    // Half width of the body around each joint
+    widths: Vec<f32>,
+    // Keeps joints at least the sum of their widths apart from the rest of the body
+    pub self_avoidance: bool,
+    pub rest: Option<Rest>,
+    // The last joint links back to the first, link_sizes then has one entry per joint
+    closed: bool,
+    // Links without an entry stay rigid
+    elastic: Vec<Option<Elastic>>,
+    // Joints without an entry bend as fast as they are pulled
+    angular_limits: Vec<Option<AngularLimit>>,
+    bend_velocities: Vec<f32>,
+}
+
+// Caps how fast the bend at a joint may change while resolving
+#[derive(Clone, Copy)]
+pub struct AngularLimit {
+    // In radians per second
+    pub velocity: f32,
+    // In radians per second squared, None changes speed instantly
+    pub acceleration: Option<f32>,
+}
+
+// Lets a link stretch and squash around its rest length while resolving
+#[derive(Clone, Copy)]
+pub struct Elastic {
+    // Rate at which the link springs back to its rest length, per second
+    pub stiffness: f32,
+    // Shortest and longest the link may get, as fractions of its rest length
+    pub min: f32,
+    pub max: f32,
+}
+
+// Shape the body relaxes back towards while resolving, the head always stays put
+#[derive(Clone)]
+pub struct Rest {
+    pub pose: Pose,
+    // Rate at which the body bends back towards pose, per second
+    pub stiffness: f32,
+}
+
+// Verlet follow mode for resolve, joints keep their velocity between steps
+#[derive(Clone, Copy)]
+pub struct Dynamics {
+    // Rate at which velocity decays, per second
+    pub damping: f32,
+    // Rate at which joints are pulled towards where the kinematic follow would put them, per second
+    pub stiffness: f32,
+    // In pixels per second squared
+    pub gravity: Vector2,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum IkSolver {
+    Fabrik,
+    Ccd,
+    // Exact solve for 3 joint chains, others fall back to FABRIK
+    TwoBone(Bend),
+}
+
+// Which way the middle joint of a two bone chain points
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum Bend {
+    // Bend towards a point in world space
+    Pole(Vector2),
+    // Positive bends the elbow the way a positive angle turns, relative to the anchor to target line
+    Sign(f32),
+}
+
+// Allowed bend of a link relative to the link before it, in radians
+#[derive(Clone, Copy)]
+pub struct JointLimit {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl JointLimit {
+    pub fn new(min: f32, max: f32) -> Self {
+        JointLimit { min, max }
+    }
+
+    pub fn symmetric(constraint: f32) -> Self {
+        JointLimit { min: -constraint, max: constraint }
+    }
+}
+
+#[allow(dead_code)]
+pub struct IkResult {
+    pub iterations: usize,
+    pub error: f32,
+    pub reached: bool,
+}
+
+impl IkResult {
+    // What a solver reports when it refuses a target it can't make sense of
+    fn unsolved() -> Self {
+        IkResult {
+            iterations: 0,
+            error: f32::INFINITY,
+            reached: false,
+        }
+    }
 }
 
 impl Chain {
     pub fn new(origin: Vector2, joint_count: usize, link_size: f32, angle_constraint: f32) -> Self {
+        assert!(joint_count >= 2, "a chain needs at least two joints");
+        Chain::with_lengths(origin, &vec![link_size; joint_count - 1], angle_constraint)
+    }
+
+    // link_sizes[i] is the distance between joints i and i + 1
+    pub fn with_lengths(origin: Vector2, link_sizes: &[f32], angle_constraint: f32) -> Self {
+        assert!(!link_sizes.is_empty(), "a chain needs at least one link");
         let mut joints = vec![origin];
-        let mut angles = vec![0.0];
-        for i in 1..joint_count {
-            joints.push(Vector2::new(joints[i-1].x, joints[i-1].y + link_size));
-            angles.push(0.0);
+        let mut angles = vec![Angle::default()];
+        for i in 1..=link_sizes.len() {
+            joints.push(Vector2::new(joints[i-1].x, joints[i-1].y + link_sizes[i-1]));
+            angles.push(Angle::default());
         }
         Chain {
+            prev_joints: joints.clone(),
             joints,
-            link_size,
+            link_sizes: link_sizes.to_vec(),
             angles,
-            angle_constraint,
+            limits: vec![JointLimit::symmetric(angle_constraint); link_sizes.len() + 1],
+            solver: IkSolver::Fabrik,
+            tolerance: 0.5,
+            max_iterations: 10,
+            dynamics: None,
+            prev_dt: 0.0,
+            obstacles: Vec::new(),
+            collision_radius: 0.0,
+            widths: vec![0.0; link_sizes.len() + 1],
+            self_avoidance: false,
+            rest: None,
+            closed: false,
+            elastic: vec![None; link_sizes.len()],
+            angular_limits: vec![None; link_sizes.len() + 1],
+            bend_velocities: vec![0.0; link_sizes.len() + 1],
         }
     }
 
-    pub fn resolve(&mut self, pos: Vector2) {
-        self.angles[0] = (pos - self.joints[0]).heading();
-        self.joints[0] = pos;
+    // Joints evenly spaced around a circle, rings only move through resolve and resolve_from
+    pub fn ring(center: Vector2, joint_count: usize, link_size: f32, angle_constraint: f32) -> Self {
+        assert!(joint_count >= 3, "a ring needs at least three joints");
+        let mut chain = Chain::new(center, joint_count, link_size, angle_constraint);
+        let radius = link_size / (2.0 * (std::f32::consts::PI / joint_count as f32).sin());
+        for (i, joint) in chain.joints.iter_mut().enumerate() {
+            *joint = center + Vector2::from_angle(Angle::new(2.0 * std::f32::consts::PI * i as f32 / joint_count as f32)) * radius;
+        }
+        chain.prev_joints = chain.joints.clone();
+        chain.link_sizes.push(link_size);
+        chain.elastic.push(None);
+        chain.closed = true;
+        chain.update_angles();
+        chain
+    }
+
+    // Limits the bend at joint i; limits[i] constrains link i against link i - 1
+    pub fn set_limit(&mut self, i: usize, limit: JointLimit) {
+        self.limits[i] = limit;
+    }
+
+    // Missing widths repeat the last one given
+    pub fn set_widths(&mut self, widths: &[f32]) {
+        for i in 0..self.widths.len() {
+            self.widths[i] = widths[i.min(widths.len() - 1)];
+        }
+    }
+
+    // Elastic links keep their volume, so the body thins where it stretches
+    pub fn width(&self, i: usize) -> f32 {
+        self.widths[i] / self.stretch(i).sqrt()
+    }
+
+    // Smooth curve through the joints with the body's current widths, to place things between joints
+    pub fn spine_curve(&self) -> SpineCurve {
+        let widths: Vec<f32> = (0..self.joints.len()).map(|i| self.width(i)).collect();
+        SpineCurve::new(&self.joints, &widths, self.closed)
+    }
+
+    // Current length over rest length of the links on either side of joint i
+    pub fn stretch(&self, i: usize) -> f32 {
+        let count = self.joints.len();
+        let before = if i > 0 { Some(i - 1) } else if self.closed { Some(count - 1) } else { None };
+        let after = if i < self.link_sizes.len() { Some(i) } else { None };
+
+        let mut total = 0.0;
+        let mut links = 0.0;
+        for k in [before, after].into_iter().flatten() {
+            total += self.joints[(k + 1) % count].distance(self.joints[k]) / self.link_sizes[k];
+            links += 1.0;
+        }
+        if links > 0.0 { total / links } else { 1.0 }
+    }
+
+    // Limits how fast the bend at joint i may change, see set_limit
+    pub fn set_angular_limit(&mut self, i: usize, limit: AngularLimit) {
+        self.angular_limits[i] = Some(limit);
+    }
+
+    // Makes link i, from joint i to joint i + 1, stretchy
+    pub fn set_elastic(&mut self, i: usize, elastic: Elastic) {
+        self.elastic[i] = Some(elastic);
+    }
+
+    // Adds a joint at the tail, carrying on in the direction of the last link
+    pub fn push_segment(&mut self, link_size: f32, width: f32) {
+        if self.closed {
+            return;
+        }
+        let last = self.joints.len() - 1;
+        let joint = self.joints[last] - Vector2::from_angle(self.angles[last]) * link_size;
+        self.joints.push(joint);
+        self.prev_joints.push(joint);
+        self.angles.push(self.angles[last]);
+        self.limits.push(self.limits[last]);
+        self.widths.push(width);
+        self.link_sizes.push(link_size);
+        self.elastic.push(self.elastic[last - 1]);
+        self.angular_limits.push(self.angular_limits[last]);
+        self.bend_velocities.push(0.0);
+    }
+
+    // Removes the tail joint, a chain always keeps at least two
+    pub fn pop_segment(&mut self) {
+        if self.closed || self.joints.len() <= 2 {
+            return;
+        }
+        self.joints.pop();
+        self.prev_joints.pop();
+        self.angles.pop();
+        self.limits.pop();
+        self.widths.pop();
+        self.link_sizes.pop();
+        self.elastic.pop();
+        self.angular_limits.pop();
+        self.bend_velocities.pop();
+    }
+
+    // Redistributes the chain over joint_count evenly spaced joints along its current shape
+    #[allow(dead_code)]
+    pub fn resample(&mut self, joint_count: usize) {
+        assert!(joint_count >= 2, "a chain needs at least two joints");
+        if self.closed {
+            return;
+        }
+        let link_size = self.length() / (joint_count - 1) as f32;
+
+        let mut joints = Vec::with_capacity(joint_count);
+        let mut limits = Vec::with_capacity(joint_count);
+        let mut angular_limits = Vec::with_capacity(joint_count);
+        let mut widths = Vec::with_capacity(joint_count);
+        let mut link = 0;
+        let mut link_start = 0.0;
+        for j in 0..joint_count {
+            let along = link_size * j as f32;
+            while link < self.link_sizes.len() - 1 && along > link_start + self.link_sizes[link] {
+                link_start += self.link_sizes[link];
+                link += 1;
+            }
+            let t = ((along - link_start) / self.link_sizes[link]).clamp(0.0, 1.0);
+            joints.push(self.joints[link].lerp(self.joints[link + 1], t));
+            let nearest = if t < 0.5 { link } else { link + 1 };
+            limits.push(self.limits[nearest]);
+            angular_limits.push(self.angular_limits[nearest]);
+            widths.push(self.widths[link] + (self.widths[link + 1] - self.widths[link]) * t);
+        }
+
+        self.prev_joints = joints.clone();
+        self.joints = joints;
+        self.limits = limits;
+        self.angular_limits = angular_limits;
+        self.bend_velocities = vec![0.0; joint_count];
+        self.widths = widths;
+        self.link_sizes = vec![link_size; joint_count - 1];
+        self.elastic = (0..joint_count - 1).map(|j| self.elastic[j * self.elastic.len() / (joint_count - 1)]).collect();
+        self.angles.resize(joint_count, Angle::default());
+        let head_angle = self.angles[0];
+        self.update_angles();
+        self.angles[0] = head_angle;
+    }
+
+    // Heading of the head followed by the bend of every link relative to the one before it
+    pub fn relative_angles(&self) -> Vec<f32> {
+        let mut relative = vec![self.angles[0].radians()];
+        for i in 1..self.angles.len() {
+            relative.push(self.angles[i].diff(self.angles[i-1]));
+        }
+        relative
+    }
+
+    // Lays the chain out from root using angles shaped like relative_angles, missing bends are straight
+    // and a missing heading keeps the current one. Elastic links keep however far they are stretched
+    pub fn forward(&mut self, root: Vector2, relative: &[f32]) -> &[Vector2] {
+        let mut old_joint = self.joints[0];
+        self.joints[0] = root;
+        if let Some(&heading) = relative.first() {
+            self.angles[0] = Angle::new(heading);
+        }
         for i in 1..self.joints.len() {
-            let cur_angle = (self.joints[i-1] - self.joints[i]).heading();
-            self.angles[i] = constrain_angle(cur_angle, self.angles[i-1], self.angle_constraint);
-            self.joints[i] = self.joints[i-1] - Vector2::from_angle(self.angles[i]) * self.link_size;
+            let length = match self.elastic[i-1] {
+                Some(_) => self.joints[i].distance(old_joint),
+                None => self.link_sizes[i-1],
+            };
+            old_joint = self.joints[i];
+            self.angles[i] = self.angles[i-1] + relative.get(i).copied().unwrap_or(0.0);
+            self.joints[i] = self.joints[i-1] - Vector2::from_angle(self.angles[i]) * length;
         }
+        &self.joints
     }
 
-    pub fn fabrik_resolve(&mut self, pos: Vector2, anchor: Vector2) {
-        // Forward pass
+    // Moves the body towards pose by weight, keeping the head where it is
+    pub fn blend_pose(&mut self, pose: &Pose, weight: f32) {
+        let relative = self.relative_angles();
+        let blended = Pose::new(relative[1..].to_vec()).blend(pose, weight);
+        let mut angles = vec![relative[0]];
+        angles.extend(blended.bends);
+        let head = self.joints[0];
+        self.forward(head, &angles);
+    }
+
+    pub fn resolve(&mut self, pos: Vector2, dt: f32) {
+        // A NaN target would spread to every joint and never wash out
+        if !pos.is_finite() {
+            return;
+        }
+        if self.closed {
+            self.resolve_ring(0, pos);
+            return;
+        }
+
+        // Relax before following so the head and the obstacles get the final say
+        if let Some(rest) = self.rest.take() {
+            self.blend_pose(&rest.pose, 1.0 - (-rest.stiffness * dt).exp());
+            self.rest = Some(rest);
+        }
+
+        let old_bends = self.relative_angles();
+        let pos = self.push_out(pos);
+        // A head that stands still keeps facing the way it was going
+        let step = pos - self.joints[0];
+        if step.try_normalize().is_some() {
+            self.angles[0] = step.heading();
+        }
+        self.prev_joints[0] = self.joints[0];
         self.joints[0] = pos;
         for i in 1..self.joints.len() {
-            self.joints[i] = constrain_distance(self.joints[i], self.joints[i-1], self.link_size);
+            let mut joint = self.joints[i];
+            if let Some(dynamics) = self.dynamics {
+                // Scale last step's displacement to this step's length
+                let scale = if self.prev_dt > 0.0 { dt / self.prev_dt } else { 0.0 };
+                let velocity = (self.joints[i] - self.prev_joints[i]) * (scale * (-dynamics.damping * dt).exp());
+                let predicted = self.joints[i] + velocity + dynamics.gravity * (dt * dt);
+                let follow = self.follow(i, self.joints[i], self.link_sizes[i-1]);
+                joint = predicted + (follow - predicted) * (1.0 - (-dynamics.stiffness * dt).exp());
+            }
+            self.prev_joints[i] = self.joints[i];
+            self.place(i, i - 1, joint, &old_bends, dt);
+        }
+        self.prev_dt = dt;
+    }
+
+    // Puts joint i as close to pos as it may go while hanging off its already placed neighbour from
+    // (i - 1 or i + 1), minding elastic links, joint limits, turn rates, the rest of the body and the obstacles.
+    // old_bends are the chain's relative_angles before this step
+    fn place(&mut self, i: usize, from: usize, pos: Vector2, old_bends: &[f32], dt: f32) {
+        let length = self.link_length(i.min(from), from, pos, dt);
+        let mut joint = if from < i {
+            let joint = self.follow(i, pos, length);
+            match self.angular_limits[i] {
+                Some(limit) => {
+                    let bend = (self.joints[i-1] - joint).heading().diff(self.angles[i-1]);
+                    let bend = self.limit_turn(i, bend, old_bends[i], limit, dt);
+                    self.joints[i-1] - Vector2::from_angle(self.angles[i-1] + bend) * length
+                }
+                None => joint,
+            }
+        } else {
+            let joint = self.lead(i, pos, length);
+            match self.angular_limits.get(i + 2).copied().flatten() {
+                Some(limit) => {
+                    let next_angle = (self.joints[i+1] - self.joints[i+2]).heading();
+                    let bend = next_angle.diff((joint - self.joints[i+1]).heading());
+                    let bend = self.limit_turn(i + 2, bend, old_bends[i+2], limit, dt);
+                    self.joints[i+1] + Vector2::from_angle(next_angle - bend) * length
+                }
+                None => joint,
+            }
+        };
+        if self.self_avoidance {
+            joint = constrain_distance(self.avoid_self(i, from, joint), self.joints[from], length);
         }
+        self.joints[i] = self.collide(joint, self.joints[from], length);
 
-        // Backward pass
-        *self.joints.last_mut().unwrap() = anchor;
-        for i in (0..self.joints.len()-1).rev() {
-            self.joints[i] = constrain_distance(self.joints[i], self.joints[i+1], self.link_size);
+        let link = i.max(from);
+        self.angles[link] = (self.joints[link-1] - self.joints[link]).heading();
+    }
+
+    fn push_out(&self, mut pos: Vector2) -> Vector2 {
+        for shape in &self.obstacles {
+            pos = shape.push_out(pos, self.collision_radius);
+        }
+        pos
+    }
+
+    // Pushes pos out of the obstacles while keeping it length away from the joint it hangs from
+    fn collide(&self, mut pos: Vector2, from: Vector2, length: f32) -> Vector2 {
+        if self.obstacles.is_empty() {
+            return pos;
+        }
+        for _ in 0..COLLISION_ITERATIONS {
+            pos = constrain_distance(self.push_out(pos), from, length);
+        }
+        pos
+    }
+
+    // Pushes joint i away from the already placed joints on the side of from that are far enough along the body to overlap it
+    fn avoid_self(&self, i: usize, from: usize, mut pos: Vector2) -> Vector2 {
+        let count = if from < i { i } else { self.joints.len() - i - 1 };
+        let mut along = 0.0;
+        for step in 1..=count {
+            let k = if from < i { i - step } else { i + step };
+            along += self.link_sizes[if from < i { k } else { k - 1 }];
+            let min_dist = self.widths[k] + self.widths[i];
+            if along > min_dist {
+                pos = Shape::Circle { center: self.joints[k], radius: min_dist }.push_out(pos, 0.0);
+            }
+        }
+        pos
+    }
+
+    // Drags joint index to pos, the rest of the body follows it on both sides
+    pub fn resolve_from(&mut self, index: usize, pos: Vector2, dt: f32) {
+        // The chain may have shrunk since the joint was picked
+        let index = index.min(self.joints.len() - 1);
+        if !pos.is_finite() {
+            return;
+        }
+        if self.closed {
+            self.resolve_ring(index, pos);
+            return;
+        }
+        let old_bends = self.relative_angles();
+        let pos = self.push_out(pos);
+        // In dynamic mode the drag carries on as velocity once let go
+        self.prev_joints.clone_from(&self.joints);
+        let step = pos - self.joints[index];
+        if index == 0 && step.try_normalize().is_some() {
+            self.angles[0] = step.heading();
+        }
+        self.joints[index] = pos;
+
+        // Towards the head
+        for i in (0..index).rev() {
+            self.place(i, i + 1, self.joints[i], &old_bends, dt);
+        }
+        if index > 0 {
+            self.angles[0] = self.angles[1];
+        }
+
+        // Towards the tail
+        for i in index+1..self.joints.len() {
+            self.place(i, i - 1, self.joints[i], &old_bends, dt);
+        }
+
+        // The bends at the held joint go wherever the drag takes them, so turn rates carry on from what really happened
+        let bends = self.relative_angles();
+        for (k, velocity) in self.bend_velocities.iter_mut().enumerate().skip(1) {
+            *velocity = if dt > 0.0 { Angle::new(bends[k]).diff(Angle::new(old_bends[k])) / dt } else { 0.0 };
+        }
+        self.prev_dt = dt;
+    }
+
+    // A ring can't be solved in one pass, so the drag spreads out from the held joint over a few rounds around the loop
+    fn resolve_ring(&mut self, index: usize, pos: Vector2) {
+        let count = self.joints.len();
+        self.prev_joints.clone_from(&self.joints);
+        // Joints near the held one get a head start, fading out to nothing on the far side of the loop
+        let pos = self.push_out(pos);
+        let shift = pos - self.joints[index];
+        for (k, joint) in self.joints.iter_mut().enumerate() {
+            let hops = ((k + count - index) % count).min((index + count - k) % count);
+            *joint += shift * (1.0 - hops as f32 / (count as f32 / 2.0)).max(0.0);
+        }
+        self.joints[index] = pos;
+
+        for _ in 0..RING_ITERATIONS {
+            // Turn joints bent past their limits halfway back, a full turn swings the rest of the loop too far
+            for step in 1..count {
+                let k = (index + step) % count;
+                let a = (k + count - 1) % count;
+                let b = (k + count - 2) % count;
+                let prev_angle = (self.joints[b] - self.joints[a]).heading();
+                let angle = (self.joints[a] - self.joints[k]).heading();
+                let limited = self.constrain_forward(k, angle, prev_angle);
+                if limited.diff(angle) != 0.0 {
+                    let angle = angle.slerp(limited, 0.5);
+                    self.joints[k] = self.push_out(self.joints[a] - Vector2::from_angle(angle) * self.link_sizes[a]);
+                }
+            }
+
+            // Then bring every link back to its length, once each way round so neither end of the loop is favoured
+            for step in 1..=count {
+                self.relax_ring_link(index, (index + step) % count);
+            }
+            for step in (1..=count).rev() {
+                self.relax_ring_link(index, (index + step) % count);
+            }
+        }
+        self.update_angles();
+    }
+
+    // Moves both ends of link k (from joint k - 1 to joint k) towards its length, the held joint never moves
+    fn relax_ring_link(&mut self, index: usize, k: usize) {
+        let count = self.joints.len();
+        let a = (k + count - 1) % count;
+        let delta = self.joints[k] - self.joints[a];
+        let Some(direction) = delta.try_normalize() else {
+            return;
+        };
+        let correction = direction * (delta.length() - self.link_sizes[a]);
+        if a == index {
+            self.joints[k] = self.push_out(self.joints[k] - correction);
+        } else if k == index {
+            self.joints[a] = self.push_out(self.joints[a] + correction);
+        } else {
+            self.joints[a] = self.push_out(self.joints[a] + correction * 0.5);
+            self.joints[k] = self.push_out(self.joints[k] - correction * 0.5);
+        }
+    }
+
+    // Index of the joint closest to pos, if any is within max_dist
+    pub fn nearest_joint(&self, pos: Vector2, max_dist: f32) -> Option<usize> {
+        self.joints
+            .iter()
+            .map(|joint| joint.distance(pos))
+            .enumerate()
+            .filter(|&(_, dist)| dist <= max_dist)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    // Where joint i ends up when pulled from pos by joint i - 1
+    fn follow(&self, i: usize, pos: Vector2, length: f32) -> Vector2 {
+        let angle = self.constrain_forward(i, (self.joints[i-1] - pos).heading(), self.angles[i-1]);
+        self.joints[i-1] - Vector2::from_angle(angle) * length
+    }
+
+    // The bend limited by k, moved from old_bend towards bend no faster than limit allows
+    fn limit_turn(&mut self, k: usize, bend: f32, old_bend: f32, limit: AngularLimit, dt: f32) -> f32 {
+        let mut velocity = if dt > 0.0 { Angle::new(bend).diff(Angle::new(old_bend)) / dt } else { 0.0 };
+        if let Some(acceleration) = limit.acceleration {
+            let previous = self.bend_velocities[k];
+            velocity = velocity.max(previous - acceleration * dt).min(previous + acceleration * dt);
+        }
+        velocity = velocity.max(-limit.velocity).min(limit.velocity);
+        self.bend_velocities[k] = velocity;
+        old_bend + velocity * dt
+    }
+
+    // Length of link when the joint at its from end stays put and the other moves to pos,
+    // elastic links give way and then spring back
+    fn link_length(&self, link: usize, from: usize, pos: Vector2, dt: f32) -> f32 {
+        let rest = self.link_sizes[link];
+        match self.elastic[link] {
+            Some(elastic) => {
+                let stretched = self.joints[from].distance(pos);
+                let length = rest + (stretched - rest) * (-elastic.stiffness * dt).exp();
+                length.max(rest * elastic.min).min(rest * elastic.max)
+            }
+            None => rest,
+        }
+    }
+
+    // Where joint i ends up when pulled from pos by joint i + 1
+    fn lead(&self, i: usize, pos: Vector2, length: f32) -> Vector2 {
+        if i + 2 >= self.joints.len() {
+            return constrain_distance(pos, self.joints[i+1], length);
+        }
+        let next_angle = (self.joints[i+1] - self.joints[i+2]).heading();
+        let angle = self.constrain_backward(i + 1, (pos - self.joints[i+1]).heading(), next_angle);
+        self.joints[i+1] + Vector2::from_angle(angle) * length
+    }
+
+    pub fn length(&self) -> f32 {
+        self.link_sizes.iter().sum()
+    }
+
+    // Moves joint 0 towards pos while keeping the last joint fixed at anchor
+    pub fn solve(&mut self, pos: Vector2, anchor: Vector2) -> IkResult {
+        match self.solver {
+            IkSolver::Fabrik => self.fabrik_resolve(pos, anchor),
+            IkSolver::Ccd => self.ccd_resolve(pos, anchor),
+            IkSolver::TwoBone(bend) => self.two_bone_resolve(pos, anchor, bend),
+        }
+    }
+
+    pub fn fabrik_resolve(&mut self, pos: Vector2, anchor: Vector2) -> IkResult {
+        self.fabrik_resolve_from(0, pos, anchor)
+    }
+
+    // Drives joint index towards pos with the last joint held at anchor, the joints before index trail behind
+    pub fn fabrik_resolve_from(&mut self, index: usize, pos: Vector2, anchor: Vector2) -> IkResult {
+        if !pos.is_finite() || !anchor.is_finite() {
+            return IkResult::unsolved();
+        }
+        let reachable = pos.distance(anchor) <= self.link_sizes[index..].iter().sum::<f32>();
+        let mut iterations = 0;
+        let mut error;
+
+        loop {
+            // Forward pass
+            self.joints[index] = pos;
+            for i in index+1..self.joints.len() {
+                let joint = if i == index + 1 {
+                    constrain_distance(self.joints[i], self.joints[i-1], self.link_sizes[i-1])
+                } else {
+                    let prev_angle = (self.joints[i-2] - self.joints[i-1]).heading();
+                    let angle = self.constrain_forward(i, (self.joints[i-1] - self.joints[i]).heading(), prev_angle);
+                    self.joints[i-1] - Vector2::from_angle(angle) * self.link_sizes[i-1]
+                };
+                self.joints[i] = self.collide(joint, self.joints[i-1], self.link_sizes[i-1]);
+            }
+
+            // Backward pass
+            let last = self.joints.len() - 1;
+            self.joints[last] = anchor;
+            for i in (index..last).rev() {
+                self.joints[i] = self.collide(self.lead(i, self.joints[i], self.link_sizes[i]), self.joints[i+1], self.link_sizes[i]);
+            }
+
+            iterations += 1;
+            error = self.joints[index].distance(pos);
+            if error <= self.tolerance || iterations >= self.max_iterations {
+                break;
+            }
+        }
+
+        for i in (0..index).rev() {
+            self.joints[i] = self.collide(self.lead(i, self.joints[i], self.link_sizes[i]), self.joints[i+1], self.link_sizes[i]);
+        }
+        self.update_angles();
+
+        IkResult {
+            iterations,
+            error,
+            reached: reachable && error <= self.tolerance,
+        }
+    }
+
+    pub fn ccd_resolve(&mut self, pos: Vector2, anchor: Vector2) -> IkResult {
+        if !pos.is_finite() || !anchor.is_finite() {
+            return IkResult::unsolved();
+        }
+        let reachable = pos.distance(anchor) <= self.length();
+        let last = self.joints.len() - 1;
+        let offset = anchor - self.joints[last];
+        for joint in self.joints.iter_mut() {
+            *joint += offset;
+        }
+
+        let mut iterations = 0;
+        let mut error;
+
+        loop {
+            // Rotate everything below each joint so the end points at pos, starting next to the end
+            for j in 1..=last {
+                let pivot = self.joints[j];
+                let mut rotation = (pos - pivot).heading().diff((self.joints[0] - pivot).heading());
+                if j < last {
+                    let link_angle = (self.joints[j-1] - pivot).heading();
+                    let prev_angle = (pivot - self.joints[j+1]).heading();
+                    rotation = self.constrain_backward(j, link_angle + rotation, prev_angle).diff(link_angle);
+                }
+                for i in 0..j {
+                    self.joints[i] = pivot + (self.joints[i] - pivot).rotate(rotation);
+                }
+            }
+
+            iterations += 1;
+            error = self.joints[0].distance(pos);
+            if error <= self.tolerance || iterations >= self.max_iterations {
+                break;
+            }
+        }
+        self.update_angles();
+
+        IkResult {
+            iterations,
+            error,
+            reached: reachable && error <= self.tolerance,
+        }
+    }
+
+    pub fn two_bone_resolve(&mut self, pos: Vector2, anchor: Vector2, bend: Bend) -> IkResult {
+        if self.joints.len() != 3 {
+            return self.fabrik_resolve(pos, anchor);
+        }
+        if !pos.is_finite() || !anchor.is_finite() {
+            return IkResult::unsolved();
+        }
+
+        let upper = self.link_sizes[1];
+        let lower = self.link_sizes[0];
+        let to_target = pos - anchor;
+        let reach = to_target.length();
+        let dist = reach.min(upper + lower).max((upper - lower).abs());
+
+        // Law of cosines for the angle between the anchor to target line and the upper bone
+        let cos_angle = if dist > 0.0 {
+            (upper * upper + dist * dist - lower * lower) / (2.0 * upper * dist)
+        } else {
+            1.0
+        };
+        let sign = match bend {
+            Bend::Sign(sign) => if sign < 0.0 { -1.0 } else { 1.0 },
+            Bend::Pole(pole) => {
+                let to_pole = pole - anchor;
+                if to_target.cross(to_pole) < 0.0 { -1.0 } else { 1.0 }
+            }
+        };
+        let heading = to_target.heading();
+        let mut upper_angle = heading + sign * cos_angle.clamp(-1.0, 1.0).acos();
+        let elbow = anchor + Vector2::from_angle(upper_angle) * upper;
+        let mut lower_angle = (anchor + Vector2::from_angle(heading) * dist - elbow).heading();
+
+        // Past the elbow limit the bend is held at the limit and the whole leg turns to point the end at the target
+        // The bend comes from the elbow's inner angle rather than the two headings, which can't tell a fully folded
+        // leg bent one way from one bent the other
+        let limit = self.limits[2];
+        let inner = ((upper * upper + lower * lower - dist * dist) / (2.0 * upper * lower)).clamp(-1.0, 1.0).acos();
+        let elbow_bend = sign * (std::f32::consts::PI - inner);
+        if elbow_bend < limit.min || elbow_bend > limit.max {
+            let elbow_bend = elbow_bend.clamp(limit.min, limit.max);
+            let reach = Vector2::new(upper, 0.0) + Vector2::from_angle(Angle::new(-elbow_bend)) * lower;
+            upper_angle = heading - reach.heading().radians();
+            lower_angle = upper_angle - elbow_bend;
+        }
+
+        self.joints[2] = anchor;
+        self.joints[1] = anchor + Vector2::from_angle(upper_angle) * upper;
+        self.joints[0] = self.joints[1] + Vector2::from_angle(lower_angle) * lower;
+        self.update_angles();
+
+        let error = self.joints[0].distance(pos);
+        IkResult {
+            iterations: 1,
+            error,
+            reached: error <= self.tolerance,
+        }
+    }
+
+    // Link i (from joint i to joint i - 1) against link i - 1, when solving from the head
+    fn constrain_forward(&self, i: usize, angle: Angle, prev_angle: Angle) -> Angle {
+        let limit = self.limits[i];
+        angle.clamp_around(prev_angle, limit.min, limit.max)
+    }
+
+    // Link i against link i + 1, when solving from the tail
+    fn constrain_backward(&self, i: usize, angle: Angle, next_angle: Angle) -> Angle {
+        let limit = self.limits[i + 1];
+        angle.clamp_around(next_angle, -limit.max, -limit.min)
+    }
+
+    fn update_angles(&mut self) {
+        for i in 1..self.joints.len() {
+            self.angles[i] = (self.joints[i-1] - self.joints[i]).heading();
+        }
+        if self.closed {
+            self.angles[0] = (self.joints[self.joints.len() - 1] - self.joints[0]).heading();
+        } else if self.joints.len() > 1 {
+            self.angles[0] = self.angles[1];
+        }
+    }
+
+    // Blends from an earlier state of this chain, alpha 0 being previous and 1 being self
+    pub fn interpolate(&self, previous: &Chain, alpha: f32) -> Chain {
+        let mut chain = self.clone();
+        for (joint, prev) in chain.joints.iter_mut().zip(&previous.joints) {
+            *joint = prev.lerp(*joint, alpha);
+        }
+        for (angle, prev) in chain.angles.iter_mut().zip(&previous.angles) {
+            *angle = prev.slerp(*angle, alpha);
         }
+        chain
     }
 
     pub fn display(&self, canvas: &mut Canvas<Window>) {
-        for i in 0..self.joints.len() - 1 {
+        for i in 0..self.link_sizes.len() {
             let start = self.joints[i];
-            let end = self.joints[i + 1];
+            let end = self.joints[(i + 1) % self.joints.len()];
             canvas.thick_line(start.x as i16, start.y as i16, end.x as i16, end.y as i16, 8, Color::RGB(255, 255, 255)).unwrap();
         }
 
-        for joint in &self.joints {
-            canvas.filled_circle(joint.x as i16, joint.y as i16, 16, Color::RGB(42, 44, 53)).unwrap();
+        for (i, joint) in self.joints.iter().enumerate() {
+            // Keep joints on short links from swallowing their neighbours
+            let shortest = self.link_sizes[i.saturating_sub(1)..(i + 1).min(self.link_sizes.len())]
+                .iter()
+                .fold(32.0_f32, |acc, &size| acc.min(size));
+            let radius = (shortest / 2.0) as i16;
+            canvas.filled_circle(joint.x as i16, joint.y as i16, radius, Color::RGB(42, 44, 53)).unwrap();
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+// collision.rs
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use crate::util::Vector2;
+
+#[derive(Clone)]
+pub enum Shape {
+    Circle { center: Vector2, radius: f32 },
+    Capsule { start: Vector2, end: Vector2, radius: f32 },
+    // Convex, in either winding order
+    Polygon(Vec<Vector2>),
+}
+
+impl Shape {
+    // Moves a circle of the given radius at pos out of the shape, pos is returned as is when they don't overlap
+    pub fn push_out(&self, pos: Vector2, radius: f32) -> Vector2 {
+        match self {
+            Shape::Circle { center, radius: size } => push_from_point(pos, *center, size + radius),
+            Shape::Capsule { start, end, radius: size } => push_from_point(pos, closest_on_segment(pos, *start, *end), size + radius),
+            Shape::Polygon(points) => {
+                // Outward normals depend on the winding order
+                let winding = if signed_area(points) < 0.0 { -1.0 } else { 1.0 };
+                let mut best_dist = f32::MIN;
+                let mut best_normal = Vector2::default();
+                for i in 0..points.len() {
+                    let edge = points[(i + 1) % points.len()] - points[i];
+                    let normal = Vector2::new(edge.y, -edge.x).normalize() * winding;
+                    let dist = (pos - points[i]).dot(normal);
+                    if dist > best_dist {
+                        best_dist = dist;
+                        best_normal = normal;
+                    }
+                }
+
+                if best_dist >= radius {
+                    pos
+                } else if best_dist <= 0.0 {
+                    // Inside or on the outline, the nearest face is the shortest way out
+                    pos + best_normal * (radius - best_dist)
+                } else {
+                    // Outside but close, the nearest point of the outline may be a corner rather than a face
+                    let closest = (0..points.len())
+                        .map(|i| closest_on_segment(pos, points[i], points[(i + 1) % points.len()]))
+                        .min_by(|a, b| pos.distance(*a).total_cmp(&pos.distance(*b)))
+                        .unwrap_or(pos);
+                    push_from_point(pos, closest, radius)
+                }
+            }
+        }
+    }
+
+    pub fn display(&self, canvas: &mut Canvas<Window>, color: Color) {
+        match self {
+            Shape::Circle { center, radius } => {
+                canvas.filled_circle(center.x as i16, center.y as i16, *radius as i16, color).unwrap();
+            }
+            Shape::Capsule { start, end, radius } => {
+                canvas.thick_line(start.x as i16, start.y as i16, end.x as i16, end.y as i16, (radius * 2.0) as u8, color).unwrap();
+                canvas.filled_circle(start.x as i16, start.y as i16, *radius as i16, color).unwrap();
+                canvas.filled_circle(end.x as i16, end.y as i16, *radius as i16, color).unwrap();
+            }
+            Shape::Polygon(points) => {
+                let x_points: Vec<i16> = points.iter().map(|p| p.x as i16).collect();
+                let y_points: Vec<i16> = points.iter().map(|p| p.y as i16).collect();
+                canvas.filled_polygon(&x_points, &y_points, color).unwrap();
+            }
+        }
+    }
+}
+
+fn push_from_point(pos: Vector2, point: Vector2, distance: f32) -> Vector2 {
+    if pos.distance(point) >= distance {
+        pos
+    } else {
+        // A circle dead on the point has no way out, so it leaves along +x
+        point + (pos - point).try_normalize().unwrap_or(Vector2::new(1.0, 0.0)) * distance
+    }
+}
+
+fn closest_on_segment(pos: Vector2, start: Vector2, end: Vector2) -> Vector2 {
+    let segment = end - start;
+    let length_sq = segment.dot(segment);
+    if length_sq == 0.0 {
+        return start;
+    }
+    start + segment * ((pos - start).dot(segment) / length_sq).clamp(0.0, 1.0)
+}
+
+fn signed_area(points: &[Vector2]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        area += a.cross(b);
+    }
+    area / 2.0
+}
@@ -3,41 +3,81 @@ use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::pixels::Color;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
-use crate::chain::Chain;
+use crate::chain::{Chain, Dynamics, Elastic, Rest};
+use crate::pose::Pose;
+use crate::spline::{SpineCurve, SpineSample};
 use crate::util::Vector2;
 
+// Head speed in pixels per second
+const SPEED: f32 = 960.0;
+// Where the fins sit, as a fraction of the way from the head to the tail
+const PECTORAL_FINS: f32 = 0.27;
+const VENTRAL_FINS: f32 = 0.64;
+
+#[derive(Clone)]
 pub struct Fish {
     pub spine: Chain,
     body_color: Color,
     fin_color: Color,
-    body_width: Vec<f32>,
 }
 
 impl Fish {
     pub fn new(origin: Vector2) -> Self {
+        let mut spine = Chain::new(origin, 12, 64.0, std::f32::consts::PI / 8.0);
+        spine.dynamics = Some(Dynamics {
+            damping: 6.0,
+            stiffness: 40.0,
+            gravity: Vector2::default(),
+        });
+        spine.collision_radius = 40.0;
+        spine.rest = Some(Rest {
+            pose: Pose::straight(),
+            stiffness: 2.0,
+        });
+        spine.set_widths(&[68.0, 81.0, 84.0, 83.0, 77.0, 64.0, 51.0, 38.0, 32.0, 19.0]);
+        for i in 0..11 {
+            spine.set_elastic(i, Elastic { stiffness: 120.0, min: 0.85, max: 1.2 });
+        }
+
         Fish {
-            spine: Chain::new(origin, 12, 64.0, std::f32::consts::PI / 8.0),
+            spine,
             body_color: Color::RGB(58, 124, 165),
             fin_color: Color::RGB(129, 195, 215),
-            body_width: vec![68.0, 81.0, 84.0, 83.0, 77.0, 64.0, 51.0, 38.0, 32.0, 19.0],
         }
     }
 
-    pub fn resolve(&mut self, mouse_x: f32, mouse_y: f32) {
+    pub fn resolve(&mut self, mouse_x: f32, mouse_y: f32, dt: f32) {
         let head_pos = self.spine.joints[0];
         let mouse_pos = Vector2::new(mouse_x, mouse_y);
-        let target_pos = head_pos + (mouse_pos - head_pos).set_mag(16.0);
-        self.spine.resolve(target_pos);
+        let to_mouse = mouse_pos - head_pos;
+        let target_pos = head_pos + to_mouse.set_mag((SPEED * dt).min(to_mouse.length()));
+        self.spine.resolve(target_pos, dt);
+    }
+
+    // Holds joint of the spine at the given position, the body follows it
+    pub fn drag(&mut self, joint: usize, x: f32, y: f32, dt: f32) {
+        self.spine.resolve_from(joint, Vector2::new(x, y), dt);
+    }
+
+    pub fn interpolate(&self, previous: &Fish, alpha: f32) -> Fish {
+        Fish {
+            spine: self.spine.interpolate(&previous.spine, alpha),
+            ..self.clone()
+        }
     }
 
     pub fn display(&self, canvas: &mut Canvas<Window>) {
+        let curve = self.spine.spine_curve();
+
         // Pectoral fins
-        self.draw_fin(canvas, 3, std::f32::consts::PI / 3.0, 0.0, -std::f32::consts::PI / 4.0, 160.0, 64.0);
-        self.draw_fin(canvas, 3, -std::f32::consts::PI / 3.0, 0.0, std::f32::consts::PI / 4.0, 160.0, 64.0);
+        let pectoral = curve.sample(curve.length() * PECTORAL_FINS);
+        self.draw_fin(canvas, pectoral, std::f32::consts::PI / 3.0, 0.0, -std::f32::consts::PI / 4.0, 160.0, 64.0);
+        self.draw_fin(canvas, pectoral, -std::f32::consts::PI / 3.0, 0.0, std::f32::consts::PI / 4.0, 160.0, 64.0);
 
         // Ventral fins
-        self.draw_fin(canvas, 7, std::f32::consts::PI / 2.0, 0.0, -std::f32::consts::PI / 4.0, 96.0, 32.0);
-        self.draw_fin(canvas, 7, -std::f32::consts::PI / 2.0, 0.0, std::f32::consts::PI / 4.0, 96.0, 32.0);
+        let ventral = curve.sample(curve.length() * VENTRAL_FINS);
+        self.draw_fin(canvas, ventral, std::f32::consts::PI / 2.0, 0.0, -std::f32::consts::PI / 4.0, 96.0, 32.0);
+        self.draw_fin(canvas, ventral, -std::f32::consts::PI / 2.0, 0.0, std::f32::consts::PI / 4.0, 96.0, 32.0);
 
         // Caudal fin
         self.draw_caudal_fin(canvas);
@@ -49,11 +89,11 @@ impl Fish {
         self.draw_dorsal_fin(canvas);
 
         // Eyes
-        self.draw_eyes(canvas);
+        self.draw_eyes(canvas, &curve);
     }
 
-    fn draw_fin(&self, canvas: &mut Canvas<Window>, i: usize, angle_offset: f32, length_offset: f32, rotation: f32, width: f32, height: f32) {
-        let pos = self.get_pos(i, angle_offset, length_offset);
+    fn draw_fin(&self, canvas: &mut Canvas<Window>, at: SpineSample, angle_offset: f32, length_offset: f32, rotation: f32, width: f32, height: f32) {
+        let pos = at.offset(angle_offset, length_offset);
         canvas.filled_ellipse(pos.x as i16, pos.y as i16, (width / 2.0) as i16, (height / 2.0) as i16, self.fin_color).unwrap();
     }
 
@@ -69,20 +109,11 @@ impl Fish {
         //dorsal fin drawing
     }
 
-    fn draw_eyes(&self, canvas: &mut Canvas<Window>) {
-        let right_eye = self.get_pos(0, std::f32::consts::PI / 2.0, -18.0);
-        let left_eye = self.get_pos(0, -std::f32::consts::PI / 2.0, -18.0);
+    fn draw_eyes(&self, canvas: &mut Canvas<Window>, curve: &SpineCurve) {
+        let head = curve.at_joint(0);
+        let right_eye = head.offset(std::f32::consts::PI / 2.0, -18.0);
+        let left_eye = head.offset(-std::f32::consts::PI / 2.0, -18.0);
         canvas.filled_circle(right_eye.x as i16, right_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
         canvas.filled_circle(left_eye.x as i16, left_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
     }
-
-    fn get_pos(&self, i: usize, angle_offset: f32, length_offset: f32) -> Vector2 {
-        let joint = self.spine.joints[i];
-        let angle = self.spine.angles[i];
-        let width = self.body_width[i.min(self.body_width.len() - 1)];
-        Vector2::new(
-            joint.x + (angle + angle_offset).cos() * (width + length_offset),
-            joint.y + (angle + angle_offset).sin() * (width + length_offset),
-        )
-    }
 }
@@ -3,77 +3,152 @@ use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::pixels::Color;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
-use crate::chain::Chain;
-use crate::util::Vector2;
+use crate::chain::{Bend, Chain, IkResult, IkSolver, JointLimit};
+use crate::skeleton::{Attachment, Skeleton};
+use crate::spline::SpineCurve;
+use crate::util::{Angle, Vector2};
 
+// Head speed in pixels per second
+const SPEED: f32 = 600.0;
+// Rate at which feet close in on their planted spot, per second
+const STEP_RATE: f32 = 30.0;
+
+#[derive(Clone)]
 pub struct Lizard {
-    pub spine: Chain,
-    arms: Vec<Chain>,
+    skeleton: Skeleton,
+    // Resting spot of each foot, in the frame of its leg's attachment
+    feet: Vec<Vector2>,
     arm_desired: Vec<Vector2>,
-    body_width: Vec<f32>,
 }
 
 impl Lizard {
     pub fn new(origin: Vector2) -> Self {
-        let mut arms = Vec::new();
+        let body_width = vec![52.0, 58.0, 40.0, 60.0, 68.0, 71.0, 65.0, 50.0, 28.0, 15.0, 11.0, 9.0, 7.0, 7.0];
+
+        // Stiffer neck than the rest of the spine
+        let mut spine = Chain::new(origin, 14, 64.0, std::f32::consts::PI / 8.0);
+        spine.set_limit(1, JointLimit::symmetric(std::f32::consts::PI / 16.0));
+        spine.set_limit(2, JointLimit::symmetric(std::f32::consts::PI / 16.0));
+        spine.collision_radius = 40.0;
+        spine.set_widths(&body_width);
+        let mut skeleton = Skeleton::new(spine);
+
+        let mut feet = Vec::new();
         let mut arm_desired = Vec::new();
         for i in 0..4 {
-            arms.push(Chain::new(origin, 3, if i < 2 { 52.0 } else { 36.0 }, std::f32::consts::PI));
-            arm_desired.push(Vector2::new(0.0, 0.0));
+            // Lower leg first, the chain runs from the foot to the shoulder
+            let lengths = if i < 2 { [48.0, 56.0] } else { [30.0, 42.0] };
+            let mut arm = Chain::with_lengths(origin, &lengths, 3.0 * std::f32::consts::PI / 4.0);
+
+            // Elbows point backwards and knees forwards, mirrored on each side
+            let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+            let bend = if i < 2 { side } else { -side };
+            let max_bend = 3.0 * std::f32::consts::PI / 4.0;
+            arm.set_limit(2, if bend > 0.0 { JointLimit::new(0.0, max_bend) } else { JointLimit::new(-max_bend, 0.0) });
+            arm.solver = IkSolver::TwoBone(Bend::Sign(bend));
+
+            // Front legs hang off the shoulders, back legs off the hips
+            let body_index = if i < 2 { 3 } else { 7 };
+            let angle = if i < 2 { std::f32::consts::PI / 4.0 } else { std::f32::consts::PI / 3.0 };
+            let shoulder = Vector2::from_angle(Angle::new(std::f32::consts::PI / 2.0 * side)) * (body_width[body_index] - 20.0);
+            let foot = Vector2::from_angle(Angle::new(angle * side)) * (body_width[body_index] + 80.0);
+            let reach = foot - shoulder;
+
+            skeleton.attach(arm, Attachment {
+                parent: 0,
+                joint: body_index,
+                offset: shoulder,
+                angle: reach.heading().radians(),
+            });
+            feet.push(Vector2::new(reach.length(), 0.0));
+            arm_desired.push(Vector2::default());
         }
 
         Lizard {
-            spine: Chain::new(origin, 14, 64.0, std::f32::consts::PI / 8.0),
-            arms,
+            skeleton,
+            feet,
             arm_desired,
-            body_width: vec![52.0, 58.0, 40.0, 60.0, 68.0, 71.0, 65.0, 50.0, 28.0, 15.0, 11.0, 9.0, 7.0, 7.0],
         }
     }
 
-    pub fn resolve(&mut self, mouse_x: f32, mouse_y: f32) {
-        let head_pos = self.spine.joints[0];
+    pub fn spine(&self) -> &Chain {
+        self.skeleton.root()
+    }
+
+    pub fn spine_mut(&mut self) -> &mut Chain {
+        &mut self.skeleton.chains[0]
+    }
+
+    pub fn resolve(&mut self, mouse_x: f32, mouse_y: f32, dt: f32) {
+        let head_pos = self.spine().joints[0];
         let mouse_pos = Vector2::new(mouse_x, mouse_y);
-        let target_pos = head_pos + (mouse_pos - head_pos).set_mag(10.0);
-        self.spine.resolve(target_pos);
+        let to_mouse = mouse_pos - head_pos;
+        let target_pos = head_pos + to_mouse.set_mag((SPEED * dt).min(to_mouse.length()));
 
-        for i in 0..self.arms.len() {
-            let side = if i % 2 == 0 { 1.0 } else { -1.0 };
-            let body_index = if i < 2 { 3 } else { 7 };
-            let angle = if i < 2 { std::f32::consts::PI / 4.0 } else { std::f32::consts::PI / 3.0 };
-            let desired_pos = self.get_pos(body_index, angle * side, 80.0);
+        let results = self.skeleton.solve(target_pos, dt, Lizard::foot_target(&self.feet, &mut self.arm_desired, dt));
+        self.replant(&results);
+    }
 
-            if (desired_pos - self.arm_desired[i]).length() > 200.0 {
-                self.arm_desired[i] = desired_pos;
+    // Holds joint of a chain at the given position. Chain 0 is the spine, which the body and legs follow,
+    // the others are legs that bend from their shoulder while the body stays put
+    pub fn drag(&mut self, chain: usize, joint: usize, x: f32, y: f32, dt: f32) {
+        let pos = Vector2::new(x, y);
+        let target = Lizard::foot_target(&self.feet, &mut self.arm_desired, dt);
+        let results = if chain == 0 {
+            self.skeleton.solve_from(joint, pos, dt, target)
+        } else {
+            self.skeleton.solve_limb(chain, joint, pos, target)
+        };
+        self.replant(&results);
+    }
+
+    // Chain and joint closest to pos, legs included
+    pub fn nearest_joint(&self, pos: Vector2, max_dist: f32) -> Option<(usize, usize)> {
+        self.skeleton.nearest_joint(pos, max_dist)
+    }
+
+    // Where each foot reaches for, it steps to a new spot once it falls too far behind
+    fn foot_target<'a>(feet: &'a [Vector2], arm_desired: &'a mut [Vector2], dt: f32) -> impl FnMut(usize, &Skeleton) -> Vector2 + 'a {
+        move |i, skeleton| {
+            let desired_pos = skeleton.local_to_world(i, feet[i - 1]);
+            if desired_pos.distance(arm_desired[i - 1]) > 200.0 {
+                arm_desired[i - 1] = desired_pos;
+            }
+
+            let start = skeleton.chains[i].joints[0];
+            start + (arm_desired[i - 1] - start) * (1.0 - (-STEP_RATE * dt).exp())
+        }
+    }
+
+    // Re-plants feet that can no longer reach their spot
+    fn replant(&mut self, results: &[Option<IkResult>]) {
+        for (i, result) in results.iter().enumerate() {
+            if result.as_ref().is_some_and(|result| !result.reached) {
+                self.arm_desired[i - 1] = self.skeleton.local_to_world(i, self.feet[i - 1]);
             }
+        }
+    }
 
-            let start = self.arms[i].joints[0];
-            let end = self.get_pos(body_index, std::f32::consts::PI / 2.0 * side, -20.0);
-            let target = start + (self.arm_desired[i] - start) * 0.4;
-            self.arms[i].fabrik_resolve(target, end);
+    pub fn interpolate(&self, previous: &Lizard, alpha: f32) -> Lizard {
+        Lizard {
+            skeleton: self.skeleton.interpolate(&previous.skeleton, alpha),
+            ..self.clone()
         }
     }
 
     pub fn display(&self, canvas: &mut Canvas<Window>) {
+        let curve = self.spine().spine_curve();
         self.draw_arms(canvas);
-        self.draw_body(canvas);
-        self.draw_eyes(canvas);
-        //self.spine.display(canvas);
+        self.draw_body(canvas, &curve);
+        self.draw_eyes(canvas, &curve);
+        //self.spine().display(canvas);
     }
 
     fn draw_arms(&self, canvas: &mut Canvas<Window>) {
-        for (i, arm) in self.arms.iter().enumerate() {
+        for arm in self.skeleton.chains.iter().skip(1) {
             let shoulder = arm.joints[2];
+            let elbow = arm.joints[1];
             let foot = arm.joints[0];
-            let mut elbow = arm.joints[1];
-
-            let para = foot - shoulder;
-            let perp = Vector2::new(-para.y, para.x).set_mag(30.0);
-
-            if i == 2 {
-                elbow = elbow - perp;
-            } else if i == 3 {
-                elbow = elbow + perp;
-            }
 
             canvas.thick_line(shoulder.x as i16, shoulder.y as i16, elbow.x as i16, elbow.y as i16, 40, Color::RGB(255, 255, 255)).unwrap();
             canvas.thick_line(elbow.x as i16, elbow.y as i16, foot.x as i16, foot.y as i16, 40, Color::RGB(255, 255, 255)).unwrap();
@@ -82,25 +157,26 @@ impl Lizard {
         }
     }
 
-    fn draw_body(&self, canvas: &mut Canvas<Window>) {
+    fn draw_body(&self, canvas: &mut Canvas<Window>, curve: &SpineCurve) {
         let mut points: Vec<(i16, i16)> = Vec::new();
 
         // Right half of the lizard
-        for i in 0..self.spine.joints.len() {
-            let pos = self.get_pos(i, std::f32::consts::PI / 2.0, 0.0);
+        for i in 0..self.spine().joints.len() {
+            let pos = curve.at_joint(i).offset(std::f32::consts::PI / 2.0, 0.0);
             points.push((pos.x as i16, pos.y as i16));
         }
 
         // Left half of the lizard
-        for i in (0..self.spine.joints.len()).rev() {
-            let pos = self.get_pos(i, -std::f32::consts::PI / 2.0, 0.0);
+        for i in (0..self.spine().joints.len()).rev() {
+            let pos = curve.at_joint(i).offset(-std::f32::consts::PI / 2.0, 0.0);
             points.push((pos.x as i16, pos.y as i16));
         }
 
         // Top of the head
-        let head_top1 = self.get_pos(0, -std::f32::consts::PI / 6.0, -8.0);
-        let head_top2 = self.get_pos(0, 0.0, -6.0);
-        let head_top3 = self.get_pos(0, std::f32::consts::PI / 6.0, -8.0);
+        let head = curve.at_joint(0);
+        let head_top1 = head.offset(-std::f32::consts::PI / 6.0, -8.0);
+        let head_top2 = head.offset(0.0, -6.0);
+        let head_top3 = head.offset(std::f32::consts::PI / 6.0, -8.0);
         points.push((head_top1.x as i16, head_top1.y as i16));
         points.push((head_top2.x as i16, head_top2.y as i16));
         points.push((head_top3.x as i16, head_top3.y as i16));
@@ -110,20 +186,11 @@ impl Lizard {
                       Color::RGB(172, 57, 49)).unwrap();
     }
 
-    fn draw_eyes(&self, canvas: &mut Canvas<Window>) {
-        let right_eye = self.get_pos(0, 3.0 * std::f32::consts::PI / 5.0, -7.0);
-        let left_eye = self.get_pos(0, -3.0 * std::f32::consts::PI / 5.0, -7.0);
+    fn draw_eyes(&self, canvas: &mut Canvas<Window>, curve: &SpineCurve) {
+        let head = curve.at_joint(0);
+        let right_eye = head.offset(3.0 * std::f32::consts::PI / 5.0, -7.0);
+        let left_eye = head.offset(-3.0 * std::f32::consts::PI / 5.0, -7.0);
         canvas.filled_circle(right_eye.x as i16, right_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
         canvas.filled_circle(left_eye.x as i16, left_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
     }
-
-    fn get_pos(&self, i: usize, angle_offset: f32, length_offset: f32) -> Vector2 {
-        let joint = self.spine.joints[i];
-        let angle = self.spine.angles[i];
-        let width = self.body_width[i.min(self.body_width.len() - 1)];
-        Vector2::new(
-            joint.x + (angle + angle_offset).cos() * (width + length_offset),
-            joint.y + (angle + angle_offset).sin() * (width + length_offset),
-        )
-    }
 }
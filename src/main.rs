@@ -2,15 +2,22 @@ use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::gfx::framerate::{self, FPSManager};
-use std::time::Duration;
+use std::time::Instant;
 
+mod blob;
 mod chain;
+mod collision;
 mod fish;
 mod snake;
 mod lizard;
+mod pose;
+mod skeleton;
+mod spline;
 mod toy;
 mod util;
 
+use blob::Blob;
+use collision::Shape;
 use fish::Fish;
 use snake::Snake;
 use lizard::Lizard;
@@ -22,6 +29,13 @@ const SCREEN_HEIGHT: u32 = 1080;
 
 const RATE: u32 = 60;
 
+// Simulation runs in fixed steps of this many seconds, independent of the frame rate
+const STEP: f32 = 1.0 / 120.0;
+// Longest frame time that gets simulated, so a stall doesn't snowball into more steps
+const MAX_FRAME_TIME: f32 = 0.25;
+// How close to a joint a click has to land to grab it
+const GRAB_RADIUS: f32 = 40.0;
+
 fn main() -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
@@ -39,52 +53,152 @@ fn main() -> Result<(), String> {
     let mut fish = Fish::new(Vector2::new(SCREEN_WIDTH as f32 / 2.0, SCREEN_HEIGHT as f32 / 2.0));
     let mut snake = Snake::new(Vector2::new(SCREEN_WIDTH as f32 / 2.0, SCREEN_HEIGHT as f32 / 2.0));
     let mut lizard = Lizard::new(Vector2::new(SCREEN_WIDTH as f32 / 2.0, SCREEN_HEIGHT as f32 / 2.0));
-    let mut toy = Toy::new(Vector2::new(SCREEN_WIDTH as f32 / 3.0, SCREEN_HEIGHT as f32 / 3.0));
+    let mut blob = Blob::new(Vector2::new(SCREEN_WIDTH as f32 / 2.0, SCREEN_HEIGHT as f32 / 2.0));
+    let mut toy = Toy::new(Vector2::new(SCREEN_WIDTH as f32 / 3.0, SCREEN_HEIGHT as f32 / 3.0), Vector2::new(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32));
+
+    // Rocks every creature has to find its way around
+    let rocks = vec![
+        Shape::Circle { center: Vector2::new(360.0, 280.0), radius: 90.0 },
+        Shape::Capsule { start: Vector2::new(1200.0, 220.0), end: Vector2::new(1420.0, 420.0), radius: 50.0 },
+        Shape::Polygon(vec![
+            Vector2::new(420.0, 760.0),
+            Vector2::new(620.0, 700.0),
+            Vector2::new(680.0, 880.0),
+            Vector2::new(480.0, 940.0),
+        ]),
+    ];
+    fish.spine.obstacles = rocks.clone();
+    snake.spine.obstacles = rocks.clone();
+    lizard.spine_mut().obstacles = rocks.clone();
+    blob.spine.obstacles = rocks.clone();
+
+    // Creature states before the latest step, rendering interpolates towards the current ones
+    let mut prev_fish = fish.clone();
+    let mut prev_snake = snake.clone();
+    let mut prev_lizard = lizard.clone();
+    let mut prev_blob = blob.clone();
 
     let mut animal = 0;
+    // Chain and joint of the active creature held by the mouse, only the lizard has more than one chain
+    let mut grabbed: Option<(usize, usize)> = None;
+    let mut last_frame = Instant::now();
+    let mut accumulator = 0.0;
 
     'running: loop {
         println!("{}", fps.get_framerate());
+        // Set when the creature or its handling changes, so nothing interpolates from a stale snapshot
+        let mut reset_snapshots = false;
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
-                Event::MouseButtonDown {..} => {
-                    animal = (animal + 1) % 3;
+                Event::MouseButtonDown { x, y, .. } => {
+                    let pos = Vector2::new(x as f32, y as f32);
+                    // Clicking a joint grabs it, clicking anywhere else switches creature
+                    grabbed = match animal {
+                        0 => fish.spine.nearest_joint(pos, GRAB_RADIUS).map(|joint| (0, joint)),
+                        1 => snake.spine.nearest_joint(pos, GRAB_RADIUS).map(|joint| (0, joint)),
+                        2 => lizard.nearest_joint(pos, GRAB_RADIUS),
+                        3 => blob.spine.nearest_joint(pos, GRAB_RADIUS).map(|joint| (0, joint)),
+                        _ => unreachable!(),
+                    };
+                    if grabbed.is_none() {
+                        animal = (animal + 1) % 4;
+                    }
+                    reset_snapshots = true;
+                },
+                Event::MouseButtonUp {..} => {
+                    grabbed = None;
+                    reset_snapshots = true;
                 },
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } => snake.grow(),
+                Event::KeyDown { keycode: Some(Keycode::Down), .. } => snake.shrink(),
+                Event::KeyDown { keycode: Some(Keycode::Num1), .. } => snake.set_pose("straight"),
+                Event::KeyDown { keycode: Some(Keycode::Num2), .. } => snake.set_pose("curled"),
+                Event::KeyDown { keycode: Some(Keycode::Num3), .. } => snake.set_pose("s-curve"),
                 _ => {}
             }
         }
 
+        if reset_snapshots {
+            prev_fish = fish.clone();
+            prev_snake = snake.clone();
+            prev_lizard = lizard.clone();
+            prev_blob = blob.clone();
+        }
+
         canvas.set_draw_color(Color::RGB(40, 44, 52));
         canvas.clear();
 
+        for rock in &rocks {
+            rock.display(&mut canvas, Color::RGB(68, 71, 90));
+        }
+
         let mouse_state = event_pump.mouse_state();
         let (mouse_x, mouse_y) = (mouse_state.x() as f32, mouse_state.y() as f32);
 
+        // Bank the time since the last frame and spend it in fixed steps
+        let now = Instant::now();
+        accumulator += (now - last_frame).as_secs_f32().min(MAX_FRAME_TIME);
+        last_frame = now;
+
+        while accumulator >= STEP {
+            match animal {
+                0 => {
+                    prev_fish = fish.clone();
+                    match grabbed {
+                        Some((_, joint)) => fish.drag(joint, mouse_x, mouse_y, STEP),
+                        None => fish.resolve(mouse_x, mouse_y, STEP),
+                    }
+                },
+                1 => {
+                    prev_snake = snake.clone();
+                    match grabbed {
+                        Some((_, joint)) => snake.drag(joint, mouse_x, mouse_y, STEP),
+                        None => snake.resolve(mouse_x, mouse_y, STEP),
+                    }
+                },
+                2 => {
+                    prev_lizard = lizard.clone();
+                    match grabbed {
+                        Some((chain, joint)) => lizard.drag(chain, joint, mouse_x, mouse_y, STEP),
+                        None => lizard.resolve(mouse_x, mouse_y, STEP),
+                    }
+                },
+                3 => {
+                    prev_blob = blob.clone();
+                    match grabbed {
+                        Some((_, joint)) => blob.drag(joint, mouse_x, mouse_y, STEP),
+                        None => blob.resolve(mouse_x, mouse_y, STEP),
+                    }
+                },
+                _ => unreachable!(),
+            }
+
+            let caught = toy.update(match animal {
+                0 => &fish.spine.joints[0],
+                1 => &snake.spine.joints[0],
+                2 => &lizard.spine().joints[0],
+                3 => &blob.spine.joints[0],
+                _ => unreachable!()
+            });
+            // The snake grows whenever it eats
+            if caught && animal == 1 {
+                snake.grow();
+            }
+            accumulator -= STEP;
+        }
+
+        // How far we are between the last two steps
+        let alpha = accumulator / STEP;
         match animal {
-            0 => {
-                fish.resolve(mouse_x as f32, mouse_y as f32);
-                fish.display(&mut canvas);
-            },
-            1 => {
-                snake.resolve(mouse_x as f32, mouse_y as f32);
-                snake.display(&mut canvas);
-            },
-            2 => {
-                lizard.resolve(mouse_x as f32, mouse_y as f32);
-                lizard.display(&mut canvas);
-            },
+            0 => fish.interpolate(&prev_fish, alpha).display(&mut canvas),
+            1 => snake.interpolate(&prev_snake, alpha).display(&mut canvas),
+            2 => lizard.interpolate(&prev_lizard, alpha).display(&mut canvas),
+            3 => blob.interpolate(&prev_blob, alpha).display(&mut canvas),
             _ => unreachable!(),
         }
-
-        toy.update(match animal {
-            0 => &fish.spine.joints[0],
-            1 => &snake.spine.joints[0],
-            2 => &lizard.spine.joints[0],
-            _ => unreachable!()
-        });
         toy.display(&mut canvas);
 
         canvas.present();
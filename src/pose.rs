@@ -0,0 +1,53 @@
+// pose.rs
+use std::collections::HashMap;
+use crate::util::Angle;
+
+// Bend of every link relative to the one before it, shaped like Chain::relative_angles without the heading
+#[derive(Clone)]
+pub struct Pose {
+    pub bends: Vec<f32>,
+}
+
+impl Pose {
+    pub fn new(bends: Vec<f32>) -> Self {
+        Pose { bends }
+    }
+
+    pub fn straight() -> Self {
+        Pose { bends: Vec::new() }
+    }
+
+    // Weight 0 gives self and 1 gives other, bends missing from either pose count as straight
+    pub fn blend(&self, other: &Pose, weight: f32) -> Pose {
+        let count = self.bends.len().max(other.bends.len());
+        let bends = (0..count)
+            .map(|i| {
+                let from = self.bends.get(i).copied().unwrap_or(0.0);
+                let to = other.bends.get(i).copied().unwrap_or(0.0);
+                Angle::new(from).slerp(Angle::new(to), weight).radians()
+            })
+            .collect();
+        Pose { bends }
+    }
+}
+
+#[derive(Clone)]
+pub struct PoseLibrary {
+    poses: HashMap<String, Pose>,
+}
+
+impl PoseLibrary {
+    pub fn new() -> Self {
+        PoseLibrary {
+            poses: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, pose: Pose) {
+        self.poses.insert(name.to_string(), pose);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Pose> {
+        self.poses.get(name)
+    }
+}
@@ -0,0 +1,119 @@
+// skeleton.rs
+use crate::chain::{Chain, IkResult};
+use crate::util::{Angle, Vector2};
+
+// Where a child chain hangs off its parent, in the frame of the parent joint
+// (x along the joint's heading)
+#[derive(Clone, Copy)]
+pub struct Attachment {
+    pub parent: usize,
+    pub joint: usize,
+    pub offset: Vector2,
+    pub angle: f32,
+}
+
+// A tree of chains, the root is driven like a spine and children are solved from their attachment
+#[derive(Clone)]
+pub struct Skeleton {
+    pub chains: Vec<Chain>,
+    attachments: Vec<Option<Attachment>>,
+}
+
+impl Skeleton {
+    pub fn new(root: Chain) -> Self {
+        Skeleton {
+            chains: vec![root],
+            attachments: vec![None],
+        }
+    }
+
+    // Parents always come before their children, so solving in order is parent first
+    pub fn attach(&mut self, chain: Chain, attachment: Attachment) -> usize {
+        assert!(attachment.parent < self.chains.len(), "parent chain must be attached first");
+        self.chains.push(chain);
+        self.attachments.push(Some(attachment));
+        self.chains.len() - 1
+    }
+
+    pub fn root(&self) -> &Chain {
+        &self.chains[0]
+    }
+
+    // World position of a point given in the frame of a joint
+    pub fn joint_to_world(&self, chain: usize, joint: usize, local: Vector2) -> Vector2 {
+        let chain = &self.chains[chain];
+        chain.joints[joint] + local.rotate(chain.angles[joint].radians())
+    }
+
+    // World position and heading of the attachment of chain i
+    pub fn anchor(&self, i: usize) -> (Vector2, Angle) {
+        match self.attachments[i] {
+            Some(attachment) => (
+                self.joint_to_world(attachment.parent, attachment.joint, attachment.offset),
+                self.chains[attachment.parent].angles[attachment.joint] + attachment.angle,
+            ),
+            None => (self.chains[i].joints[0], self.chains[i].angles[0]),
+        }
+    }
+
+    // World position of a point given in the frame of chain i's attachment
+    pub fn local_to_world(&self, i: usize, local: Vector2) -> Vector2 {
+        let (pos, heading) = self.anchor(i);
+        pos + local.rotate(heading.radians())
+    }
+
+    // Moves the root towards pos, then every child towards the target picked for it once its parent is solved
+    pub fn solve(&mut self, pos: Vector2, dt: f32, target: impl FnMut(usize, &Skeleton) -> Vector2) -> Vec<Option<IkResult>> {
+        self.chains[0].resolve(pos, dt);
+        self.solve_children(None, target)
+    }
+
+    // Same as solve but drags a joint of the root instead of leading with its head
+    pub fn solve_from(&mut self, joint: usize, pos: Vector2, dt: f32, target: impl FnMut(usize, &Skeleton) -> Vector2) -> Vec<Option<IkResult>> {
+        self.chains[0].resolve_from(joint, pos, dt);
+        self.solve_children(None, target)
+    }
+
+    // Holds a joint of child chain i at pos while the root stays put, the other children are solved as usual
+    pub fn solve_limb(&mut self, i: usize, joint: usize, pos: Vector2, target: impl FnMut(usize, &Skeleton) -> Vector2) -> Vec<Option<IkResult>> {
+        self.solve_children(Some((i, joint, pos)), target)
+    }
+
+    // Chain and joint closest to pos, if any is within max_dist. A child's last joint moves with its parent
+    // so it can't be picked
+    pub fn nearest_joint(&self, pos: Vector2, max_dist: f32) -> Option<(usize, usize)> {
+        self.chains
+            .iter()
+            .enumerate()
+            .flat_map(|(i, chain)| {
+                let count = if self.attachments[i].is_some() { chain.joints.len() - 1 } else { chain.joints.len() };
+                chain.joints[..count].iter().enumerate().map(move |(joint, &at)| (i, joint, at.distance(pos)))
+            })
+            .filter(|&(_, _, dist)| dist <= max_dist)
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(i, joint, _)| (i, joint))
+    }
+
+    fn solve_children(&mut self, held: Option<(usize, usize, Vector2)>, mut target: impl FnMut(usize, &Skeleton) -> Vector2) -> Vec<Option<IkResult>> {
+        let mut results = vec![None];
+        for i in 1..self.chains.len() {
+            let (anchor, _) = self.anchor(i);
+            let result = match held {
+                Some((chain, joint, pos)) if chain == i => self.chains[i].fabrik_resolve_from(joint, pos, anchor),
+                _ => {
+                    let goal = target(i, self);
+                    self.chains[i].solve(goal, anchor)
+                }
+            };
+            results.push(Some(result));
+        }
+        results
+    }
+
+    pub fn interpolate(&self, previous: &Skeleton, alpha: f32) -> Skeleton {
+        Skeleton {
+            chains: self.chains.iter().zip(&previous.chains).map(|(chain, prev)| chain.interpolate(prev, alpha)).collect(),
+            attachments: self.attachments.clone(),
+        }
+    }
+}
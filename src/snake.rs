@@ -3,79 +3,156 @@ use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::pixels::Color;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
-use crate::chain::Chain;
+use crate::chain::{AngularLimit, Chain, Elastic, Rest};
+use crate::pose::{Pose, PoseLibrary};
+use crate::spline::{draw_spline_polygon, SpineCurve, SplineKind};
 use crate::util::Vector2;
 
+// Head speed in pixels per second
+const SPEED: f32 = 480.0;
+// Seconds the head has to rest before the body starts curling up
+const IDLE_DELAY: f32 = 0.5;
+// Rate at which a resting body settles into its curl, per second
+const CURL_RATE: f32 = 1.5;
+// Rate at which a moving body straightens out, per second
+const STRAIGHTEN_RATE: f32 = 0.3;
+// Longest the body grows, self-avoidance gets slow past this
+const MAX_JOINTS: usize = 96;
+
+#[derive(Clone)]
 pub struct Snake {
     pub spine: Chain,
+    poses: PoseLibrary,
+    idle: f32,
 }
 
 impl Snake {
     pub fn new(origin: Vector2) -> Self {
+        let mut spine = Chain::new(origin, 48, 64.0, std::f32::consts::PI / 8.0);
+        spine.collision_radius = 40.0;
+        spine.set_widths(&(0..48).map(Snake::body_width).collect::<Vec<f32>>());
+        spine.self_avoidance = true;
+        for i in 0..47 {
+            spine.set_elastic(i, Elastic { stiffness: 100.0, min: 0.85, max: 1.2 });
+        }
+        // Bends take a moment to travel down the body
+        for i in 1..48 {
+            spine.set_angular_limit(i, AngularLimit { velocity: 4.0, acceleration: Some(40.0) });
+        }
+        spine.rest = Some(Rest {
+            pose: Pose::straight(),
+            stiffness: STRAIGHTEN_RATE,
+        });
+
+        let mut poses = PoseLibrary::new();
+        poses.insert("straight", Pose::straight());
+        // Spiral around the head, tightest at the neck
+        poses.insert("curled", Pose::new((1..48).map(|i| std::f32::consts::PI / 8.0 * (1.0 - 0.7 * i as f32 / 48.0)).collect()));
+        poses.insert("s-curve", Pose::new((1..48).map(|i| std::f32::consts::PI / 10.0 * (i as f32 * std::f32::consts::PI / 12.0).sin()).collect()));
+
         Snake {
-            spine: Chain::new(origin, 48, 64.0, std::f32::consts::PI / 8.0),
+            spine,
+            poses,
+            idle: 0.0,
         }
     }
 
-    pub fn resolve(&mut self, mouse_x: f32, mouse_y: f32) {
+    pub fn resolve(&mut self, mouse_x: f32, mouse_y: f32, dt: f32) {
         let head_pos = self.spine.joints[0];
         let mouse_pos = Vector2::new(mouse_x, mouse_y);
-        let target_pos = head_pos + (mouse_pos - head_pos).set_mag(8.0);
-        self.spine.resolve(target_pos);
+        let to_mouse = mouse_pos - head_pos;
+        let target_pos = head_pos + to_mouse.set_mag((SPEED * dt).min(to_mouse.length()));
+
+        if to_mouse.length() > 1.0 {
+            self.idle = 0.0;
+        } else {
+            self.idle += dt;
+        }
+        // A resting body relaxes into its curl instead of straightening out
+        let (name, stiffness) = if self.idle > IDLE_DELAY { ("curled", CURL_RATE) } else { ("straight", STRAIGHTEN_RATE) };
+        if let Some(pose) = self.poses.get(name) {
+            self.spine.rest = Some(Rest { pose: pose.clone(), stiffness });
+        }
+
+        self.spine.resolve(target_pos, dt);
+    }
+
+    // Holds joint of the spine at the given position, the body follows it
+    pub fn drag(&mut self, joint: usize, x: f32, y: f32, dt: f32) {
+        self.idle = 0.0;
+        self.spine.resolve_from(joint, Vector2::new(x, y), dt);
+    }
+
+    pub fn grow(&mut self) {
+        if self.spine.joints.len() >= MAX_JOINTS {
+            return;
+        }
+        let width = Snake::body_width(self.spine.joints.len());
+        self.spine.push_segment(64.0, width);
+    }
+
+    pub fn shrink(&mut self) {
+        self.spine.pop_segment();
+    }
+
+    // Snaps the body straight into one of its poses
+    pub fn set_pose(&mut self, name: &str) {
+        if let Some(pose) = self.poses.get(name) {
+            self.spine.blend_pose(pose, 1.0);
+        }
+    }
+
+    pub fn interpolate(&self, previous: &Snake, alpha: f32) -> Snake {
+        Snake {
+            spine: self.spine.interpolate(&previous.spine, alpha),
+            ..self.clone()
+        }
     }
 
     pub fn display(&self, canvas: &mut Canvas<Window>) {
         canvas.set_draw_color(Color::RGB(172, 57, 49));
 
         // Draw body
+        let curve = self.spine.spine_curve();
         let mut points: Vec<(f32, f32)> = Vec::new();
         
         // Right half of the snake
         for i in 0..self.spine.joints.len() {
-            let pos = self.get_pos(i, std::f32::consts::PI / 2.0, 0.0);
+            let pos = curve.at_joint(i).offset(std::f32::consts::PI / 2.0, 0.0);
             points.push((pos.x as f32, pos.y as f32));
         }
 
         // Left half of the snake
         for i in (0..self.spine.joints.len()).rev() {
-            let pos = self.get_pos(i, -std::f32::consts::PI / 2.0, 0.0);
+            let pos = curve.at_joint(i).offset(-std::f32::consts::PI / 2.0, 0.0);
             points.push((pos.x as f32, pos.y as f32));
         }
 
-        // Nose of snake
-        let pos = self.get_pos(0_usize, 0.0, 0.0);
+        // Nose of snake, the outline closes back onto the right half from here
+        let pos = curve.at_joint(0).offset(0.0, 0.0);
         points.push((pos.x as f32, pos.y as f32));
 
-        crate::util::draw_spline_polygon(canvas, &points, 50, Color::RGB(172, 57, 49));
+        draw_spline_polygon(canvas, &points, SplineKind::CatmullRom, 0.5, true, Color::RGB(172, 57, 49));
 
         
         // Draw eyes
-        self.draw_eyes(canvas);
+        self.draw_eyes(canvas, &curve);
         //self.spine.display(canvas);
     }
 
-    fn draw_eyes(&self, canvas: &mut Canvas<Window>) {
-        let right_eye = self.get_pos(0, std::f32::consts::PI / 2.0, -18.0);
-        let left_eye = self.get_pos(0, -std::f32::consts::PI / 2.0, -18.0);
+    fn draw_eyes(&self, canvas: &mut Canvas<Window>, curve: &SpineCurve) {
+        let head = curve.at_joint(0);
+        let right_eye = head.offset(std::f32::consts::PI / 2.0, -18.0);
+        let left_eye = head.offset(-std::f32::consts::PI / 2.0, -18.0);
         canvas.filled_circle(right_eye.x as i16, right_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
         canvas.filled_circle(left_eye.x as i16, left_eye.y as i16, 12, Color::RGB(255, 255, 255)).unwrap();
     }
 
-    fn body_width(&self, i: usize) -> f32 {
+    fn body_width(i: usize) -> f32 {
         match i {
             0 => 76.0,
             1 => 80.0,
-            _ => 64.0 - i as f32,
+            _ => (64.0 - i as f32).max(8.0),
         }
     }
-
-    fn get_pos(&self, i: usize, angle_offset: f32, length_offset: f32) -> Vector2 {
-        let joint = self.spine.joints[i];
-        let angle = self.spine.angles[i];
-        let width = self.body_width(i);
-        Vector2::new(
-            joint.x + (angle + angle_offset).cos() * (width + length_offset),
-            joint.y + (angle + angle_offset).sin() * (width + length_offset),
-        )
-    }
 }
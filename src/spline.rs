@@ -0,0 +1,267 @@
+// spline.rs
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use crate::util::Vector2;
+
+// Catmull-Rom knot spacing, 0.5 is centripetal which never cusps or loops inside a segment
+const CENTRIPETAL: f32 = 0.5;
+// Every segment is halved at least this many times, so an S bend whose middle sits on the chord still gets looked at
+const MIN_DEPTH: u32 = 1;
+// And at most this many times, 256 pieces is plenty for any on-screen segment
+const MAX_DEPTH: u32 = 8;
+// Pieces per segment in a SpineCurve's arc length table
+const ARC_SAMPLES: usize = 8;
+// Parameter step for the finite difference behind a sample's tangent
+const TANGENT_STEP: f32 = 0.01;
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+pub enum SplineKind {
+    // Overlapping quadratic Béziers, corners where they meet
+    Quadratic,
+    // Centripetal Catmull-Rom, passes through every point
+    CatmullRom,
+    // Uniform cubic B-spline, smoother but only passes near the points
+    BSpline,
+}
+
+// Smooth curve through (or near) points, closed curves wrap back to the first point.
+// Segments are split until every piece strays less than tolerance pixels from the curve, so straight stretches cost two vertices
+pub fn spline_points(points: &[Vector2], kind: SplineKind, tolerance: f32, closed: bool) -> Vec<Vector2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    match kind {
+        SplineKind::Quadratic => quadratic_spline(points, tolerance, closed),
+        SplineKind::CatmullRom => tessellate(padded(points, closed, false).windows(4), catmull_rom, tolerance),
+        SplineKind::BSpline => tessellate(padded(points, closed, true).windows(4), b_spline, tolerance),
+    }
+}
+
+pub fn draw_spline_polygon(canvas: &mut Canvas<Window>, input_points: &[(f32, f32)], kind: SplineKind, tolerance: f32, closed: bool, color: Color) {
+    let points: Vec<Vector2> = input_points.iter().map(|&(x, y)| Vector2::new(x, y)).collect();
+    let (x_points, y_points): (Vec<_>, Vec<_>) = spline_points(&points, kind, tolerance, closed)
+        .into_iter()
+        .map(|point| (point.x as i16, point.y as i16))
+        .unzip();
+    canvas.filled_polygon(&x_points, &y_points, color).unwrap();
+}
+
+// A point on a SpineCurve, tangent runs towards the tail and normal is the tangent turned by PI / 2
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+pub struct SpineSample {
+    pub pos: Vector2,
+    pub tangent: Vector2,
+    pub normal: Vector2,
+    pub width: f32,
+}
+
+impl SpineSample {
+    // Point at the body's width (plus length_offset) from the spine, angle_offset 0 being straight ahead
+    // and PI / 2 the right hand side
+    pub fn offset(&self, angle_offset: f32, length_offset: f32) -> Vector2 {
+        self.pos + Vector2::from_angle((-self.tangent).heading() + angle_offset) * (self.width + length_offset)
+    }
+}
+
+// Centripetal Catmull-Rom through the joints of a chain, sampled by distance from the head.
+// A distance doesn't depend on how many joints there are, but it slides along a body that stretches
+#[derive(Clone)]
+pub struct SpineCurve {
+    control: Vec<Vector2>,
+    widths: Vec<f32>,
+    // Distance from the head at every ARC_SAMPLES-th of a segment
+    arc: Vec<f32>,
+    closed: bool,
+}
+
+impl SpineCurve {
+    // One width per point, a closed curve has a last segment running back to the first point
+    pub fn new(points: &[Vector2], widths: &[f32], closed: bool) -> Self {
+        assert!(points.len() >= 2, "a spine needs at least two points");
+        let mut curve = SpineCurve {
+            control: padded(points, closed, false),
+            widths: widths.to_vec(),
+            arc: vec![0.0],
+            closed,
+        };
+        let pieces = curve.segments() * ARC_SAMPLES;
+        let mut previous = curve.point(0.0);
+        for k in 1..=pieces {
+            let point = curve.point(k as f32 / ARC_SAMPLES as f32);
+            curve.arc.push(curve.arc[k - 1] + point.distance(previous));
+            previous = point;
+        }
+        curve
+    }
+
+    pub fn length(&self) -> f32 {
+        self.arc[self.arc.len() - 1]
+    }
+
+    // Distance along the curve of point i
+    pub fn joint_distance(&self, i: usize) -> f32 {
+        self.arc[(i * ARC_SAMPLES).min(self.arc.len() - 1)]
+    }
+
+    pub fn at_joint(&self, i: usize) -> SpineSample {
+        self.sample(self.joint_distance(i))
+    }
+
+    // s is clamped to the ends of an open curve and wraps around a closed one
+    pub fn sample(&self, s: f32) -> SpineSample {
+        let s = if !s.is_finite() {
+            0.0
+        } else if self.closed {
+            s.rem_euclid(self.length())
+        } else {
+            s.clamp(0.0, self.length())
+        };
+        let k = self.arc.partition_point(|&along| along <= s).clamp(1, self.arc.len() - 1);
+        let piece = self.arc[k] - self.arc[k - 1];
+        let frac = if piece > 0.0 { (s - self.arc[k - 1]) / piece } else { 0.0 };
+        let u = ((k - 1) as f32 + frac) / ARC_SAMPLES as f32;
+
+        let segment = (u as usize).min(self.segments() - 1);
+        let (before, after) = if self.closed {
+            (u - TANGENT_STEP, u + TANGENT_STEP)
+        } else {
+            ((u - TANGENT_STEP).max(0.0), (u + TANGENT_STEP).min(self.segments() as f32))
+        };
+        let tangent = (self.point(after) - self.point(before))
+            .try_normalize()
+            .or_else(|| (self.control[segment + 2] - self.control[segment + 1]).try_normalize())
+            .unwrap_or(Vector2::new(1.0, 0.0));
+        // Widths blend by how far along the segment s is, not by its parameter
+        let (start, end) = (self.arc[segment * ARC_SAMPLES], self.arc[(segment + 1) * ARC_SAMPLES]);
+        let along = if end > start { (s - start) / (end - start) } else { 0.0 };
+        let width = self.widths[segment] + (self.widths[(segment + 1) % self.widths.len()] - self.widths[segment]) * along;
+
+        SpineSample {
+            pos: self.point(u),
+            tangent,
+            normal: Vector2::new(-tangent.y, tangent.x),
+            width,
+        }
+    }
+
+    fn segments(&self) -> usize {
+        self.control.len() - 3
+    }
+
+    // Point at u segments from the start, wrapping around a closed curve
+    fn point(&self, u: f32) -> Vector2 {
+        let segments = self.segments();
+        let u = if self.closed { u.rem_euclid(segments as f32) } else { u };
+        let segment = (u.max(0.0) as usize).min(segments - 1);
+        catmull_rom(&self.control[segment..segment + 4], u - segment as f32)
+    }
+}
+
+// Adds the neighbours the first and last segments need, every window of four then makes one segment
+fn padded(points: &[Vector2], closed: bool, clamp: bool) -> Vec<Vector2> {
+    let n = points.len();
+    let mut padded = Vec::with_capacity(n + 3);
+    if closed {
+        padded.push(points[n - 1]);
+        padded.extend_from_slice(points);
+        padded.extend_from_slice(&points[..2]);
+    } else if clamp {
+        // A B-spline only reaches an end point that is repeated three times
+        padded.extend_from_slice(&[points[0], points[0]]);
+        padded.extend_from_slice(points);
+        padded.extend_from_slice(&[points[n - 1], points[n - 1]]);
+    } else {
+        // Mirrored phantom points keep the ends heading along the first and last links
+        padded.push(points[0] * 2.0 - points[1]);
+        padded.extend_from_slice(points);
+        padded.push(points[n - 1] * 2.0 - points[n - 2]);
+    }
+    padded
+}
+
+// Each window of control points is one segment, evaluated by segment for t in [0, 1]
+fn tessellate<'a>(windows: impl Iterator<Item = &'a [Vector2]>, segment: fn(&[Vector2], f32) -> Vector2, tolerance: f32) -> Vec<Vector2> {
+    let mut result = Vec::new();
+    for window in windows {
+        let start = segment(window, 0.0);
+        if result.is_empty() {
+            result.push(start);
+        }
+        subdivide(window, segment, (0.0, start), (1.0, segment(window, 1.0)), tolerance, 0, &mut result);
+    }
+    result
+}
+
+// Pushes the points after start up to and including end, halving until the middle of the curve is close enough to the chord line
+fn subdivide(window: &[Vector2], segment: fn(&[Vector2], f32) -> Vector2, start: (f32, Vector2), end: (f32, Vector2), tolerance: f32, depth: u32, result: &mut Vec<Vector2>) {
+    let t = (start.0 + end.0) / 2.0;
+    let middle = segment(window, t);
+    // How far the curve strays from the chord, however unevenly the parameter runs along it
+    let deviation = match (end.1 - start.1).try_normalize() {
+        Some(direction) => (middle - start.1).cross(direction).abs(),
+        None => middle.distance(start.1),
+    };
+    let flat = deviation <= tolerance;
+    if depth >= MAX_DEPTH || (depth >= MIN_DEPTH && flat) {
+        result.push(end.1);
+        return;
+    }
+    subdivide(window, segment, start, (t, middle), tolerance, depth + 1, result);
+    subdivide(window, segment, (t, middle), end, tolerance, depth + 1, result);
+}
+
+// Point t of the way from p[1] to p[2], Barry and Goldman's pyramid over non-uniform knots
+fn catmull_rom(p: &[Vector2], t: f32) -> Vector2 {
+    // Coincident points would collapse a knot interval, so every interval is kept above zero
+    let knot = |a: Vector2, b: Vector2| a.distance(b).powf(CENTRIPETAL).max(1e-4);
+    let t0 = 0.0;
+    let t1 = t0 + knot(p[0], p[1]);
+    let t2 = t1 + knot(p[1], p[2]);
+    let t3 = t2 + knot(p[2], p[3]);
+    let t = t1 + (t2 - t1) * t;
+
+    let a1 = p[0].lerp(p[1], (t - t0) / (t1 - t0));
+    let a2 = p[1].lerp(p[2], (t - t1) / (t2 - t1));
+    let a3 = p[2].lerp(p[3], (t - t2) / (t3 - t2));
+    let b1 = a1.lerp(a2, (t - t0) / (t2 - t0));
+    let b2 = a2.lerp(a3, (t - t1) / (t3 - t1));
+    b1.lerp(b2, (t - t1) / (t2 - t1))
+}
+
+fn b_spline(p: &[Vector2], t: f32) -> Vector2 {
+    let tt = t * t;
+    let ttt = tt * t;
+    let b0 = (1.0 - t) * (1.0 - t) * (1.0 - t) / 6.0;
+    let b1 = (3.0 * ttt - 6.0 * tt + 4.0) / 6.0;
+    let b2 = (-3.0 * ttt + 3.0 * tt + 3.0 * t + 1.0) / 6.0;
+    let b3 = ttt / 6.0;
+    p[0] * b0 + p[1] * b1 + p[2] * b2 + p[3] * b3
+}
+
+fn quadratic_bezier(p: &[Vector2], t: f32) -> Vector2 {
+    p[0].lerp(p[1], t).lerp(p[1].lerp(p[2], t), t)
+}
+
+fn quadratic_spline(points: &[Vector2], tolerance: f32, closed: bool) -> Vec<Vector2> {
+    // A closed outline wraps around to its first two points
+    let wrapped: Vec<Vector2>;
+    let points = if closed {
+        wrapped = points.iter().chain(&points[..2]).copied().collect();
+        &wrapped[..]
+    } else {
+        points
+    };
+
+    let mut result = tessellate(points.windows(3), quadratic_bezier, tolerance);
+
+    // An open outline with an even point count ends on a straight piece
+    if !closed && points.len() % 2 == 0 {
+        result.push(points[points.len() - 1]);
+    }
+
+    result
+}
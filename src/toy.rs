@@ -9,19 +9,25 @@ const TARGET_DISTANCE: f32 = 30.0;
 
 pub struct Toy {
     location: Vector2,
+    // Width and height of the area the toy respawns in
+    area: Vector2,
 }
 
 impl Toy{
-    pub fn new(location: Vector2) -> Self{
+    pub fn new(location: Vector2, area: Vector2) -> Self{
         Toy {
-            location
+            location,
+            area,
         }
     }
 
-    pub fn update(&mut self, head: &Vector2){
-        if ((head.x-self.location.x)*(head.x-self.location.x) + (head.y-self.location.y)*(head.y-self.location.y)).sqrt() <= TARGET_DISTANCE{
-            self.location = Vector2::new(rand::random(), rand::random());
+    // Returns true when the head catches the toy
+    pub fn update(&mut self, head: &Vector2) -> bool{
+        if head.distance(self.location) <= TARGET_DISTANCE{
+            self.location = Vector2::new(rand::random::<f32>() * self.area.x, rand::random::<f32>() * self.area.y);
+            return true;
         }
+        false
     }
 
     pub fn display(&self, canvas: &mut Canvas<Window>){
@@ -1,13 +1,8 @@
 // util.rs
-use std::f32::consts::PI;
-use std::iter;
-use sdl2::pixels::Color;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use sdl2::gfx::primitives::DrawRenderer;
+use std::f32::consts::{PI, TAU};
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Vector2 {
     pub x: f32,
     pub y: f32,
@@ -18,27 +13,65 @@ impl Vector2 {
         Vector2 { x, y }
     }
 
-    pub fn from_angle(angle: f32) -> Self {
-        Vector2 {
-            x: angle.cos(),
-            y: angle.sin(),
-        }
+    pub fn from_angle(angle: Angle) -> Self {
+        let (sin, cos) = angle.radians().sin_cos();
+        Vector2 { x: cos, y: sin }
     }
 
-    pub fn heading(&self) -> f32 {
-        self.y.atan2(self.x)
+    pub fn heading(&self) -> Angle {
+        Angle::new(self.y.atan2(self.x))
     }
 
+    // Zero length vectors have no direction and stay zero
     pub fn set_mag(&self, length: f32) -> Self {
-        let mag = (self.x * self.x + self.y * self.y).sqrt();
-        Vector2 {
-            x: self.x * length / mag,
-            y: self.y * length / mag,
-        }
+        self.normalize() * length
     }
-    
+
     pub fn length(&self) -> f32 {
-        (self.x * self.x + self.y * self.y).sqrt()
+        self.dot(*self).sqrt()
+    }
+
+    pub fn distance(&self, other: Vector2) -> f32 {
+        (*self - other).length()
+    }
+
+    // None when the vector is too short (or not finite) to have a direction
+    pub fn try_normalize(&self) -> Option<Self> {
+        let length = self.length();
+        if length > f32::EPSILON && length.is_finite() {
+            Some(*self / length)
+        } else {
+            None
+        }
+    }
+
+    pub fn normalize(&self) -> Self {
+        self.try_normalize().unwrap_or_default()
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+
+    pub fn dot(&self, other: Vector2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    // Z component of the 3D cross product, positive when other is clockwise on screen
+    pub fn cross(&self, other: Vector2) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn lerp(&self, other: Vector2, t: f32) -> Self {
+        *self + (other - *self) * t
+    }
+
+    pub fn rotate(&self, angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Vector2 {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
+        }
     }
 }
 
@@ -53,6 +86,12 @@ impl std::ops::Add for Vector2 {
     }
 }
 
+impl std::ops::AddAssign for Vector2 {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
 impl std::ops::Sub for Vector2 {
     type Output = Self;
 
@@ -64,6 +103,12 @@ impl std::ops::Sub for Vector2 {
     }
 }
 
+impl std::ops::SubAssign for Vector2 {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
 impl std::ops::Mul<f32> for Vector2 {
     type Output = Self;
 
@@ -75,79 +120,147 @@ impl std::ops::Mul<f32> for Vector2 {
     }
 }
 
-pub fn constrain_distance(pos: Vector2, anchor: Vector2, constraint: f32) -> Vector2 {
-    anchor + (pos - anchor).set_mag(constraint)
-}
+impl std::ops::Div<f32> for Vector2 {
+    type Output = Self;
 
-pub fn constrain_angle(angle: f32, anchor: f32, constraint: f32) -> f32 {
-    let diff = relative_angle_diff(angle, anchor);
-    if diff.abs() <= constraint {
-        simplify_angle(angle)
-    } else if diff > constraint {
-        simplify_angle(anchor - constraint)
-    } else {
-        simplify_angle(anchor + constraint)
+    fn div(self, scalar: f32) -> Self {
+        Vector2 {
+            x: self.x / scalar,
+            y: self.y / scalar,
+        }
     }
 }
 
-pub fn relative_angle_diff(angle: f32, anchor: f32) -> f32 {
-    let angle = simplify_angle(angle + PI - anchor);
-    PI - angle
+impl std::ops::Neg for Vector2 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Vector2 {
+            x: -self.x,
+            y: -self.y,
+        }
+    }
 }
 
-pub fn simplify_angle(mut angle: f32) -> f32 {
-    while angle >= 2.0 * PI {
-        angle -= 2.0 * PI;
+// A direction in radians, always wrapped into (-PI, PI]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Angle(f32);
+
+impl Angle {
+    // Anything that isn't a finite number of radians becomes 0 so it can't spread
+    pub fn new(radians: f32) -> Self {
+        if radians.is_finite() {
+            // Should a remainder just below zero round up to TAU it belongs at 0, or the result would drop to -PI
+            let wrapped = (PI - radians).rem_euclid(TAU);
+            Angle(PI - if wrapped >= TAU { 0.0 } else { wrapped })
+        } else {
+            Angle(0.0)
+        }
+    }
+
+    pub fn radians(self) -> f32 {
+        self.0
+    }
+
+    // Signed shortest turn from other to self, in (-PI, PI]
+    pub fn diff(self, other: Angle) -> f32 {
+        Angle::new(self.0 - other.0).0
+    }
+
+    // Keeps self - anchor within [min, max], min being the clockwise side
+    pub fn clamp_around(self, anchor: Angle, min: f32, max: f32) -> Angle {
+        let diff = self.diff(anchor);
+        if diff < min {
+            anchor + min
+        } else if diff > max {
+            anchor + max
+        } else {
+            self
+        }
     }
-    while angle < 0.0 {
-        angle += 2.0 * PI;
+
+    // Turns from self towards other the short way round, t 0 being self and 1 being other
+    pub fn slerp(self, other: Angle, t: f32) -> Angle {
+        self + other.diff(self) * t
     }
-    angle
 }
 
+impl std::ops::Add<f32> for Angle {
+    type Output = Self;
 
-fn quadratic_bezier_points(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), steps: usize) -> Vec<(i16, i16)> {
-    let step = 1.0 / steps as f32;
-    iter::successors(Some(0.0), |&t| Some(t + step).filter(|&t| t <= 1.0))
-        .map(|t| {
-            let u = 1.0 - t;
-            let tt = t * t;
-            let uu = u * u;
-            let x = uu * p0.0 + 2.0 * u * t * p1.0 + tt * p2.0;
-            let y = uu * p0.1 + 2.0 * u * t * p1.1 + tt * p2.1;
-            (x as i16, y as i16)
-        })
-        .collect()
+    fn add(self, radians: f32) -> Self {
+        Angle::new(self.0 + radians)
+    }
 }
 
-fn generate_quadratic_bezier_from_points(points: &[(f32, f32)], steps: usize) -> Vec<(i16, i16)> {
-    if points.len() < 3 {
-        return points.iter().map(|&(x, y)| (x as i16, y as i16)).collect();
+impl std::ops::Sub<f32> for Angle {
+    type Output = Self;
+
+    fn sub(self, radians: f32) -> Self {
+        Angle::new(self.0 - radians)
     }
+}
+
+// When pos sits on the anchor there is no direction to keep, so it is pushed out along +x
+pub fn constrain_distance(pos: Vector2, anchor: Vector2, constraint: f32) -> Vector2 {
+    let direction = (pos - anchor).try_normalize().unwrap_or(Vector2::new(1.0, 0.0));
+    anchor + direction * constraint
+}
 
-    let mut result = Vec::with_capacity((points.len() - 2) * steps + 1);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for window in points.windows(3) {
-        let bezier_points = quadratic_bezier_points(window[0], window[1], window[2], steps);
-        result.extend(bezier_points.into_iter().skip(1));
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
     }
 
-    if points.len() % 2 == 0 {
-        let last_two = &points[points.len() - 2..];
-        let control_point = (
-            (last_two[0].0 + last_two[1].0) / 2.0,
-            (last_two[0].1 + last_two[1].1) / 2.0,
-        );
-        let bezier_points = quadratic_bezier_points(last_two[0], control_point, last_two[1], steps);
-        result.extend(bezier_points.into_iter().skip(1));
+    #[test]
+    fn angle_wraps_into_range() {
+        assert!(close(Angle::new(3.0 * PI).radians(), PI));
+        assert!(close(Angle::new(-PI / 2.0 - TAU).radians(), -PI / 2.0));
+        assert_eq!(Angle::new(-PI).radians(), PI);
+        assert_eq!(Angle::new(f32::NAN).radians(), 0.0);
+        assert_eq!(Angle::new(f32::INFINITY).radians(), 0.0);
     }
 
-    result
-}
+    #[test]
+    fn angle_stays_in_range_around_every_wrap() {
+        for turns in -20..20 {
+            let mut radians = PI + turns as f32 * TAU;
+            for _ in 0..64 {
+                radians = radians.next_down();
+            }
+            for _ in 0..128 {
+                radians = radians.next_up();
+                let angle = Angle::new(radians).radians();
+                assert!(angle > -PI && angle <= PI, "{radians} wrapped to {angle}");
+            }
+        }
+    }
 
+    #[test]
+    fn diff_takes_the_short_way_across_pi() {
+        let below = Angle::new(PI - 0.1);
+        let above = Angle::new(-PI + 0.1);
+        assert!(close(below.diff(above), -0.2));
+        assert!(close(above.diff(below), 0.2));
+        assert!(close(Angle::new(1.0).diff(Angle::new(0.25)), 0.75));
+    }
+
+    #[test]
+    fn clamp_around_holds_the_limits_across_pi() {
+        assert!(close(Angle::new(1.0).clamp_around(Angle::new(0.0), -0.5, 0.5).radians(), 0.5));
+        assert!(close(Angle::new(0.2).clamp_around(Angle::new(0.0), -0.5, 0.5).radians(), 0.2));
+        let anchor = Angle::new(PI - 0.1);
+        assert!(close(Angle::new(-PI + 0.3).clamp_around(anchor, -0.2, 0.2).radians(), -PI + 0.1));
+    }
 
-pub fn draw_spline_polygon(canvas: &mut Canvas<Window>, input_points: &[(f32, f32)], steps: usize, color: Color) {
-    let smooth_points = generate_quadratic_bezier_from_points(input_points, steps);
-    let (x_points, y_points): (Vec<_>, Vec<_>) = smooth_points.into_iter().unzip();
-    canvas.filled_polygon(&x_points, &y_points, color).unwrap();
+    #[test]
+    fn slerp_turns_the_short_way() {
+        let halfway = Angle::new(PI - 0.1).slerp(Angle::new(-PI + 0.1), 0.5).radians();
+        assert!(close(halfway.abs(), PI));
+        assert!(close(Angle::new(0.0).slerp(Angle::new(1.0), 0.25).radians(), 0.25));
+        assert!(close(Angle::new(0.5).slerp(Angle::new(-0.5), 1.0).radians(), -0.5));
+    }
 }